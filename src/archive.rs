@@ -0,0 +1,209 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use anyhow::{bail, Context, Result};
+use once_cell::sync::Lazy;
+use zip::result::ZipError;
+use zip::ZipArchive;
+
+use crate::search::strings::{ARCHIVE_VOLUME, RAR_PART};
+
+/// Whether an `unrar` binary can be run, checked once per run.
+static AVAILABLE: Lazy<bool> = Lazy::new(|| {
+    Command::new("unrar")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok()
+});
+
+pub fn is_available() -> bool {
+    *AVAILABLE
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArchiveKind {
+    Zip,
+    Rar,
+}
+
+/// An archive found in the inputs, with every volume of a multipart set.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Archive {
+    /// The volume to extract from, `.rar` or `.part1.rar`.
+    pub path: PathBuf,
+    /// Every file of the set, the first volume included.
+    pub volumes: Vec<PathBuf>,
+    pub kind: ArchiveKind,
+}
+
+impl Archive {
+    /// Name of the set without the volume extensions, `Movie.2010` for
+    /// `Movie.2010.part01.rar`.
+    pub fn name(&self) -> String {
+        let name = self
+            .path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let name = RAR_PART.replace(&name, "");
+        ARCHIVE_VOLUME.replace(&name, "").to_string()
+    }
+}
+
+/// Volume of an archive set: the key of its set, and whether it is the first volume.
+fn volume(path: &Path) -> Option<(PathBuf, ArchiveKind, bool)> {
+    let name = path.file_name()?.to_str()?;
+    let lowercase = name.to_lowercase();
+    let key = |stem: &str| path.with_file_name(stem);
+    if lowercase.ends_with(".zip") {
+        return Some((path.to_path_buf(), ArchiveKind::Zip, true));
+    }
+    if let Some(captures) = RAR_PART.captures(name) {
+        let first = captures[1].parse::<u32>().ok()? == 1;
+        let stem = &name[..captures.get(0)?.start()];
+        return Some((key(stem), ArchiveKind::Rar, first));
+    }
+    let captures = ARCHIVE_VOLUME.captures(name)?;
+    let stem = &name[..captures.get(0)?.start()];
+    Some((key(stem), ArchiveKind::Rar, lowercase.ends_with(".rar")))
+}
+
+/// Group the archive volumes among `files`, e.g. `Movie.rar` with `Movie.r00` and
+/// `Movie.r01`, or `Movie.part1.rar` with `Movie.part2.rar`. A set without its first
+/// volume cannot be extracted, its files are left out.
+pub fn find_archives(files: &[PathBuf]) -> Vec<Archive> {
+    let mut sets: BTreeMap<PathBuf, Vec<(PathBuf, ArchiveKind, bool)>> = BTreeMap::new();
+    for path in files {
+        if let Some((key, kind, first)) = volume(path) {
+            sets.entry(key)
+                .or_default()
+                .push((path.clone(), kind, first));
+        }
+    }
+
+    sets.into_values()
+        .filter_map(|mut volumes| {
+            volumes.sort_by(|a, b| a.0.cmp(&b.0));
+            let (path, kind, _) = volumes.iter().find(|(_, _, first)| *first)?.clone();
+            Some(Archive {
+                path,
+                volumes: volumes.into_iter().map(|(path, _, _)| path).collect(),
+                kind,
+            })
+        })
+        .collect()
+}
+
+/// Extract every file of an archive into `to`. Password protected and corrupt archives
+/// fail with the reason.
+pub fn extract(archive: &Archive, to: &Path) -> Result<()> {
+    fs::create_dir_all(to)?;
+    match archive.kind {
+        ArchiveKind::Zip => extract_zip(&archive.path, to),
+        ArchiveKind::Rar => extract_rar(&archive.path, to),
+    }
+}
+
+fn extract_zip(path: &Path, to: &Path) -> Result<()> {
+    let file = File::open(path).with_context(|| format!("Could not open {:?}", path))?;
+    let mut zip = ZipArchive::new(file).context("corrupt archive")?;
+    for index in 0..zip.len() {
+        match zip.by_index(index) {
+            Ok(entry) if entry.encrypted() => bail!("password protected"),
+            Ok(_) => {}
+            Err(ZipError::UnsupportedArchive(ZipError::PASSWORD_REQUIRED)) => {
+                bail!("password protected")
+            }
+            Err(e) => return Err(e).context("corrupt archive"),
+        }
+    }
+    zip.extract(to).context("corrupt archive")
+}
+
+/// `unrar` follows the volumes of the set by itself. `-p-` fails on a password instead of
+/// asking for one, and `-o-` never overwrites.
+fn extract_rar(path: &Path, to: &Path) -> Result<()> {
+    if !is_available() {
+        bail!("unrar is not installed");
+    }
+    let output = Command::new("unrar")
+        .args(["x", "-o-", "-p-", "-y", "-idq"])
+        .arg(path)
+        .arg(to.join(""))
+        .stdin(Stdio::null())
+        .output()
+        .context("Could not run unrar")?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        match output.status.code() {
+            Some(11) => bail!("password protected"),
+            _ if stderr.to_lowercase().contains("password") => bail!("password protected"),
+            Some(3) => bail!("corrupt archive: {}", stderr.trim()),
+            _ => bail!("unrar could not extract the archive: {}", stderr.trim()),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_archives() {
+        let files: Vec<PathBuf> = [
+            "Movie.2010.rar",
+            "Movie.2010.r00",
+            "Movie.2010.r01",
+            "Show.S01.part1.rar",
+            "Show.S01.part2.rar",
+            "Other.part2.rar",
+            "Extras.zip",
+            "Movie.2010.nfo",
+        ]
+        .iter()
+        .map(PathBuf::from)
+        .collect();
+        let archives = find_archives(&files);
+        assert_eq!(archives.len(), 3);
+        assert_eq!(archives[0].path, PathBuf::from("Extras.zip"));
+        assert_eq!(archives[0].kind, ArchiveKind::Zip);
+        assert_eq!(archives[1].path, PathBuf::from("Movie.2010.rar"));
+        assert_eq!(archives[1].volumes.len(), 3);
+        assert_eq!(archives[1].name(), "Movie.2010");
+        assert_eq!(archives[2].path, PathBuf::from("Show.S01.part1.rar"));
+        assert_eq!(archives[2].volumes.len(), 2);
+        assert_eq!(archives[2].name(), "Show.S01");
+
+        let dir = std::env::temp_dir().join("mediasort_archives");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("Movie.2010.zip");
+        let mut zip = zip::ZipWriter::new(std::fs::File::create(&path).unwrap());
+        zip.start_file(
+            "Movie.2010.1080p/Movie.2010.1080p.mkv",
+            zip::write::SimpleFileOptions::default(),
+        )
+        .unwrap();
+        std::io::Write::write_all(&mut zip, b"video").unwrap();
+        zip.finish().unwrap();
+
+        let archives = find_archives(&[path]);
+        let to = dir.join("extracted");
+        extract(&archives[0], &to).unwrap();
+        assert_eq!(
+            std::fs::read(to.join("Movie.2010.1080p/Movie.2010.1080p.mkv")).unwrap(),
+            b"video"
+        );
+
+        let corrupt = dir.join("Corrupt.zip");
+        std::fs::write(&corrupt, b"not a zip").unwrap();
+        let error = extract(&find_archives(&[corrupt])[0], &to).unwrap_err();
+        assert_eq!(error.to_string(), "corrupt archive");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
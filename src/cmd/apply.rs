@@ -0,0 +1,74 @@
+use std::fs;
+
+use anyhow::{bail, Result};
+
+use crate::cmd::sort::move_file;
+use crate::cmd::{Apply, Run};
+use crate::plan::Plan;
+
+impl Run for Apply {
+    fn run(&mut self) -> Result<()> {
+        let plan = Plan::load(&self.plan)?;
+        plan.check_sources()?;
+
+        let mut moved = 0;
+        let mut conflicts = 0;
+        let mut failures = Vec::new();
+
+        for planned in &plan.moves {
+            // The destination may have appeared since the plan was made
+            if planned.conflict || planned.destination.exists() {
+                self.verbose(&format!(
+                    "Skipping {:?}, destination already exists: {:?}",
+                    planned.source, planned.destination
+                ));
+                conflicts += 1;
+                continue;
+            }
+
+            let moved_file = (|| -> Result<()> {
+                if let Some(parent) = planned.destination.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                move_file(&planned.source, &planned.destination)
+            })();
+
+            match moved_file {
+                Ok(()) => {
+                    self.verbose(&format!(
+                        "Moved {:?} to {:?}",
+                        planned.source, planned.destination
+                    ));
+                    moved += 1;
+                }
+                Err(e) => failures.push(format!("  - {:?}: {:#}", planned.source, e)),
+            }
+        }
+
+        println!(
+            "Moved {} files, skipped {} conflicts, {} failed",
+            moved,
+            conflicts,
+            failures.len()
+        );
+
+        if !failures.is_empty() {
+            println!("\nUnsorted files:\n{}", failures.join("\n"));
+            bail!(
+                "{} of {} files could not be moved",
+                failures.len(),
+                plan.moves.len()
+            );
+        }
+
+        Ok(())
+    }
+}
+
+impl Apply {
+    fn verbose(&self, message: &str) {
+        if self.verbose {
+            println!("{}", message);
+        }
+    }
+}
@@ -1,30 +1,54 @@
 use std::path::PathBuf;
 
-use clap::{Parser, Subcommand, ValueHint};
+use clap::{Parser, Subcommand, ValueEnum, ValueHint};
+
+use crate::config::route::Route;
+use crate::convert::SubtitleFormat;
+use crate::duplicate::KeepPolicy;
+use crate::extra::SamplePolicy;
+use crate::quality::QualityProfile;
+use crate::sidecar::JunkPolicy;
+use crate::subtitle::SubtitleLayout;
 
 #[derive(Parser, Debug)]
 #[clap(about, author, version)]
 pub enum Cmd {
-    Sort(Sort),
+    Sort(Box<Sort>),
+    Apply(Apply),
+    Library(Library),
     Profile(Profile),
+    Config(Config),
 }
 
 /// Sort input media files into output directories.
-#[derive(Parser, Debug)]
+#[derive(Parser, Debug, Default)]
 #[clap(about, author)]
 pub struct Sort {
     /// Profile name.
-    #[clap(short, long, exclusive(true))]
+    /// Flags given on the command line override the profile.
+    #[clap(short, long)]
     pub profile: Option<String>,
 
-    /// Input media files.
+    /// Input directories, can be given several times.
     #[clap(short, long, value_hint = ValueHint::DirPath)]
-    pub input: Option<PathBuf>,
+    pub input: Vec<PathBuf>,
 
     /// Output directory.
     #[clap(short, long, value_hint = ValueHint::DirPath)]
     pub output: Option<PathBuf>,
 
+    /// Output root for movies, instead of {output}/{movie-template}.
+    #[clap(long, value_hint = ValueHint::DirPath)]
+    pub movies_output: Option<PathBuf>,
+
+    /// Output root for series, instead of {output}/{tv-template}.
+    #[clap(long, value_hint = ValueHint::DirPath)]
+    pub series_output: Option<PathBuf>,
+
+    /// Extra output roots, from the profile or the global config.
+    #[clap(skip)]
+    pub routes: Vec<Route>,
+
     /// Verbose mode.
     #[clap(long, action)]
     pub verbose: bool,
@@ -38,7 +62,7 @@ pub struct Sort {
     pub webhook: Option<String>,
 
     /// Recursive folders scan.
-    #[arg(long, default_value = "false")]
+    #[clap(long, action)]
     pub recursive: bool,
 
     /// Dry run.
@@ -48,12 +72,12 @@ pub struct Sort {
 
     /// TV series path template.
     /// Default: {Series}/{Name}/{Season}/{Title} - {Episode}.{Extension}
-    #[arg(long, default_value = "Series")]
+    #[arg(long)]
     pub tv_template: Option<String>,
 
     /// Movie path template.
     /// Default: {Films}/{Name} ({Year}).{Extension}
-    #[arg(long, default_value = "Films")]
+    #[arg(long)]
     pub movie_template: Option<String>,
 
     /// Search Database
@@ -67,6 +91,236 @@ pub struct Sort {
     /// If the subtitles are disabled, this option will be ignored.
     #[clap(long, action)]
     pub skip_subtitles: bool,
+
+    /// Keep policy.
+    /// Which copy to sort when the same episode or movie is found several times,
+    /// in the inputs or already in the library. (default: best)
+    #[clap(long, value_enum)]
+    pub keep: Option<KeepPolicy>,
+
+    /// Trash directory.
+    /// Move the discarded duplicates there instead of leaving them in the input.
+    #[clap(long, value_hint = ValueHint::DirPath)]
+    pub trash: Option<PathBuf>,
+
+    /// Upgrade mode.
+    /// Replace the copy already in the library when the new one is better. The old copy
+    /// is moved to the trash directory, or deleted when there is none. (default: false)
+    #[clap(long, action)]
+    pub upgrade: bool,
+
+    /// What makes a copy better, from the profile or the global config.
+    #[clap(skip)]
+    pub quality: QualityProfile,
+
+    /// Subtitle layout.
+    /// Put the subtitles in a Subtitles folder, or next to their media with the same base
+    /// name as media servers expect. (default: folder)
+    #[clap(long, value_enum)]
+    pub subtitle_layout: Option<SubtitleLayout>,
+
+    /// Subtitle format.
+    /// Convert the SRT, VTT and ASS subtitles to this format when moving them.
+    #[clap(long, value_enum)]
+    pub subtitle_format: Option<SubtitleFormat>,
+
+    /// UTF-8 subtitles.
+    /// Re-encode the text subtitles to UTF-8 when moving them, e.g. Windows-1252 SRT files
+    /// with broken accents. (default: false)
+    #[clap(long, action)]
+    pub utf8_subtitles: bool,
+
+    /// Keep original subtitles.
+    /// Keep the original file next to a converted subtitle. (default: false)
+    #[clap(long, action)]
+    pub keep_original_subtitles: bool,
+
+    /// Extract subtitles.
+    /// Write the embedded SRT and ASS subtitles of the medias next to them, unless a
+    /// subtitle of that language is already there. Needs ffmpeg. (default: false)
+    #[clap(long, action)]
+    pub extract_subtitles: bool,
+
+    /// Skip sidecars.
+    /// Leave the files sharing the base name of a media, like .nfo files and covers, in
+    /// the input instead of moving them with it. (default: false)
+    #[clap(long, action)]
+    pub skip_sidecars: bool,
+
+    /// Junk policy.
+    /// Keep or delete the junk files, like .url shortcuts or RARBG.txt, found in the
+    /// folders of the sorted medias. (default: keep)
+    #[clap(long, value_enum)]
+    pub junk: Option<JunkPolicy>,
+
+    /// Sample policy.
+    /// Leave the samples in the input, or delete them. A video is a sample when it is
+    /// named so, or under the sample size or duration when they are set. (default: skip)
+    #[clap(long, value_enum)]
+    pub samples: Option<SamplePolicy>,
+
+    /// Sample size.
+    /// Videos smaller than this many MB are samples, when ffprobe cannot tell their
+    /// duration. 0 turns it off. (default: 0)
+    #[clap(long)]
+    pub sample_size: Option<u64>,
+
+    /// Sample duration.
+    /// Videos shorter than this many seconds are samples. 0 turns it off. (default: 0)
+    #[clap(long)]
+    pub sample_duration: Option<u64>,
+
+    /// Extract archives.
+    /// Extract the .zip and .rar archives of the inputs, multipart sets included, to a
+    /// temporary folder and sort their content. RAR needs unrar. (default: false)
+    #[clap(long, action)]
+    pub extract_archives: bool,
+
+    /// Delete archives.
+    /// Delete every volume of an extracted archive once its medias are sorted.
+    /// (default: false)
+    #[clap(long, action)]
+    pub delete_archives: bool,
+
+    /// Turn off --verbose when the profile, the config or the environment turns it on.
+    #[clap(long, overrides_with = "verbose")]
+    pub no_verbose: bool,
+
+    /// Turn off --recursive when the profile, the config or the environment turns it on.
+    #[clap(long, overrides_with = "recursive")]
+    pub no_recursive: bool,
+
+    /// Turn off --dry-run when the profile, the config or the environment turns it on.
+    #[clap(long, overrides_with = "dry_run")]
+    pub no_dry_run: bool,
+
+    /// Turn off --search when the profile, the config or the environment turns it on.
+    #[clap(long, overrides_with = "search")]
+    pub no_search: bool,
+
+    /// Turn off --skip-subtitles when the profile, the config or the environment turns it on.
+    #[clap(long, overrides_with = "skip_subtitles")]
+    pub no_skip_subtitles: bool,
+
+    /// Turn off --upgrade when the profile, the config or the environment turns it on.
+    #[clap(long, overrides_with = "upgrade")]
+    pub no_upgrade: bool,
+
+    /// Turn off --utf8-subtitles when the profile, the config or the environment turns it on.
+    #[clap(long, overrides_with = "utf8_subtitles")]
+    pub no_utf8_subtitles: bool,
+
+    /// Turn off --keep-original-subtitles when the profile, the config or the environment turns it on.
+    #[clap(long, overrides_with = "keep_original_subtitles")]
+    pub no_keep_original_subtitles: bool,
+
+    /// Turn off --extract-subtitles when the profile, the config or the environment turns it on.
+    #[clap(long, overrides_with = "extract_subtitles")]
+    pub no_extract_subtitles: bool,
+
+    /// Turn off --skip-sidecars when the profile, the config or the environment turns it on.
+    #[clap(long, overrides_with = "skip_sidecars")]
+    pub no_skip_sidecars: bool,
+
+    /// Turn off --extract-archives when the profile, the config or the environment turns it on.
+    #[clap(long, overrides_with = "extract_archives")]
+    pub no_extract_archives: bool,
+
+    /// Turn off --delete-archives when the profile, the config or the environment turns it on.
+    #[clap(long, overrides_with = "delete_archives")]
+    pub no_delete_archives: bool,
+
+    /// Report file.
+    /// Write one row per input file to a JSON file, or CSV when the name ends with .csv.
+    #[clap(long, value_hint = ValueHint::FilePath)]
+    pub report: Option<PathBuf>,
+
+    /// Undo file.
+    /// Write the plan moving every sorted file back, to run with `apply --plan`.
+    #[clap(long, value_hint = ValueHint::FilePath)]
+    pub undo: Option<PathBuf>,
+
+    /// Rename files inside the output instead of moving them out of the inputs,
+    /// set by `library fix`.
+    #[clap(skip)]
+    pub in_place: bool,
+
+    /// Output format.
+    /// With json, a dry run prints the move plan to stdout, to be run later with `apply --plan`.
+    #[clap(long, value_enum, default_value_t)]
+    pub format: OutputFormat,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+/// Sorted library maintenance
+#[derive(Parser, Debug)]
+#[clap(about, author)]
+pub struct Library {
+    #[clap(subcommand)]
+    pub cmd: Option<LibraryCommand>,
+}
+
+#[derive(Clone, Debug, Subcommand)]
+pub enum LibraryCommand {
+    /// Rename and move the files of a sorted library to match the current templates.
+    Fix(Fix),
+}
+
+/// Rename and move the files of a sorted library to match the current templates.
+#[derive(Clone, Parser, Debug)]
+pub struct Fix {
+    /// Library root, the output directory it was sorted to.
+    #[clap(required(true), value_hint = ValueHint::DirPath)]
+    pub root: PathBuf,
+
+    /// Profile name, for its templates, routes and flags.
+    #[clap(short, long)]
+    pub profile: Option<String>,
+
+    /// Dry run.
+    /// Show the renames but don't actually move any files.
+    #[clap(long = "dry-run", short = 'd')]
+    pub dry_run: bool,
+
+    /// Output format, json prints the plan of a dry run.
+    #[clap(long, value_enum, default_value_t)]
+    pub format: OutputFormat,
+
+    /// Search the names in the database of TVMaze and TheMovieDB.
+    #[clap(long, action)]
+    pub search: bool,
+
+    /// Verbose mode.
+    #[clap(long, action)]
+    pub verbose: bool,
+
+    /// Report file, JSON or CSV.
+    #[clap(long, value_hint = ValueHint::FilePath)]
+    pub report: Option<PathBuf>,
+
+    /// Undo file, to run with `apply --plan`.
+    #[clap(long, value_hint = ValueHint::FilePath)]
+    pub undo: Option<PathBuf>,
+}
+
+/// Run the moves of a plan made with `sort --dry-run --format json`.
+#[derive(Parser, Debug)]
+#[clap(about, author)]
+pub struct Apply {
+    /// Plan file.
+    /// The plan is refused when any of its sources changed since it was made.
+    #[clap(long, required(true), value_hint = ValueHint::FilePath)]
+    pub plan: PathBuf,
+
+    /// Verbose mode.
+    #[clap(long, action)]
+    pub verbose: bool,
 }
 
 /// Preset profiles
@@ -91,6 +345,11 @@ pub enum ProfileCommand {
     ///List flags of a profile.
     Flags(Flags),
 
+    /// Export a profile to a file.
+    Export(Export),
+    /// Import a profile from a file.
+    Import(Import),
+
     /// Init default bad keyword
     Init(Init),
 }
@@ -106,12 +365,21 @@ pub struct Create {
     #[clap(short, long, required(true))]
     pub name: String,
 
+    /// Input directories, can be given several times.
     #[clap(short, long, required(true), value_hint = ValueHint::DirPath)]
-    pub input: PathBuf,
+    pub input: Vec<PathBuf>,
 
     #[clap(short, long, required(true), value_hint = ValueHint::DirPath)]
     pub output: PathBuf,
 
+    /// Output root for movies, instead of {output}/{movie-template}.
+    #[clap(long, value_hint = ValueHint::DirPath)]
+    pub movies_output: Option<PathBuf>,
+
+    /// Output root for series, instead of {output}/{tv-template}.
+    #[clap(long, value_hint = ValueHint::DirPath)]
+    pub series_output: Option<PathBuf>,
+
     /// Profile flags.
     #[clap(short, long)]
     pub flags: Option<Vec<String>>,
@@ -119,6 +387,10 @@ pub struct Create {
     /// Profile description.
     #[clap(short, long)]
     pub description: Option<String>,
+
+    /// Parent profile to inherit flags from.
+    #[clap(short, long)]
+    pub extends: Option<String>,
 }
 
 /// Delete a profile.
@@ -154,3 +426,65 @@ pub struct Flags {
     #[clap(short, long, required(true))]
     pub name: String,
 }
+
+/// Export a profile to a file.
+#[derive(Clone, Parser, Debug)]
+pub struct Export {
+    /// Profile name.
+    #[clap(short, long, required(true))]
+    pub name: String,
+
+    /// File to write the profile to. Prints to stdout if not given.
+    #[clap(short, long, value_hint = ValueHint::FilePath)]
+    pub file: Option<PathBuf>,
+}
+
+/// Import a profile from a file.
+#[derive(Clone, Parser, Debug)]
+pub struct Import {
+    /// File to read the profile from.
+    #[clap(short, long, required(true), value_hint = ValueHint::FilePath)]
+    pub file: PathBuf,
+
+    /// Import the profile under another name.
+    #[clap(short, long)]
+    pub name: Option<String>,
+
+    /// Replace the input directories of the imported profile.
+    #[clap(short, long, value_hint = ValueHint::DirPath)]
+    pub input: Vec<PathBuf>,
+
+    /// Replace the output directory of the imported profile.
+    #[clap(short, long, value_hint = ValueHint::DirPath)]
+    pub output: Option<PathBuf>,
+
+    /// Overwrite an existing profile with the same name.
+    #[clap(long, action)]
+    pub force: bool,
+}
+
+/// Global configuration
+#[derive(Parser, Debug)]
+#[clap(about, author)]
+pub struct Config {
+    #[clap(subcommand)]
+    pub cmd: Option<ConfigCommand>,
+}
+
+#[derive(Clone, Debug, Subcommand)]
+pub enum ConfigCommand {
+    /// Show the global config file.
+    Show(Show),
+}
+
+/// Show the global config file.
+#[derive(Clone, Parser, Debug)]
+pub struct Show {
+    /// Show every setting after resolution, with where its value comes from.
+    #[clap(long, action)]
+    pub resolved: bool,
+
+    /// Profile to resolve the settings with.
+    #[clap(short, long)]
+    pub profile: Option<String>,
+}
@@ -0,0 +1,60 @@
+use std::fs;
+
+use anyhow::{Context, Result};
+use serde_json::Map;
+
+use crate::cmd::profile::{get_profile_by_name, get_profile_properties};
+use crate::cmd::{Config, ConfigCommand, Run, Show};
+use crate::config::global::get_config_path;
+use crate::config::resolve::{resolve, setting_keys};
+
+impl Run for Config {
+    fn run(&mut self) -> Result<()> {
+        let cmd = self.cmd.as_mut().context("No subcommand provided")?;
+
+        cmd.run()?;
+
+        Ok(())
+    }
+}
+
+impl Run for ConfigCommand {
+    fn run(&mut self) -> Result<()> {
+        match self {
+            ConfigCommand::Show(cmd) => cmd.run(),
+        }
+    }
+}
+
+impl Run for Show {
+    fn run(&mut self) -> Result<()> {
+        if !self.resolved {
+            let config_path = get_config_path()?;
+            if !config_path.exists() {
+                println!("No config file found at {:?}", config_path);
+                return Ok(());
+            }
+
+            println!("# {}", config_path.display());
+            println!("{}", fs::read_to_string(&config_path)?);
+
+            return Ok(());
+        }
+
+        let profile = match &self.profile {
+            Some(name) => Some(get_profile_properties(&get_profile_by_name(name)?)?),
+            None => None,
+        };
+
+        let resolved = resolve(profile.as_ref(), Map::new())?;
+
+        for key in setting_keys() {
+            match resolved.values.get(&key) {
+                Some(value) => println!("{}: {} ({})", key, value, resolved.sources[&key]),
+                None => println!("{}: <unset>", key),
+            }
+        }
+
+        Ok(())
+    }
+}
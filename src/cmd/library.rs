@@ -0,0 +1,48 @@
+use anyhow::{bail, Context, Result};
+
+use crate::cmd::{Fix, Library, LibraryCommand, Run, Sort};
+
+impl Run for Library {
+    fn run(&mut self) -> Result<()> {
+        let cmd = self.cmd.as_mut().context("No subcommand provided")?;
+
+        cmd.run()?;
+
+        Ok(())
+    }
+}
+
+impl Run for LibraryCommand {
+    fn run(&mut self) -> Result<()> {
+        match self {
+            LibraryCommand::Fix(cmd) => cmd.run(),
+        }
+    }
+}
+
+impl Run for Fix {
+    /// The library is sorted again onto itself, so the renames get the same dry run,
+    /// plan, report and undo file as a regular sort.
+    fn run(&mut self) -> Result<()> {
+        if !self.root.is_dir() {
+            bail!("Library directory does not exist: {:?}", self.root);
+        }
+
+        let mut sort = Sort {
+            profile: self.profile.clone(),
+            input: vec![self.root.clone()],
+            output: Some(self.root.clone()),
+            recursive: true,
+            dry_run: self.dry_run,
+            format: self.format,
+            search: self.search,
+            verbose: self.verbose,
+            report: self.report.clone(),
+            undo: self.undo.clone(),
+            in_place: true,
+            ..Default::default()
+        };
+
+        sort.run()
+    }
+}
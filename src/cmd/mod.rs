@@ -1,4 +1,8 @@
+mod apply;
+#[allow(clippy::module_inception)]
 mod cmd;
+mod config;
+mod library;
 mod profile;
 #[cfg(test)]
 pub(crate) mod sort;
@@ -7,7 +11,6 @@ pub(crate) mod sort;
 #[cfg(not(test))]
 mod sort;
 
-
 use anyhow::Result;
 
 pub use crate::cmd::cmd::*;
@@ -20,7 +23,10 @@ impl Run for Cmd {
     fn run(&mut self) -> Result<()> {
         match self {
             Cmd::Sort(cmd) => cmd.run(),
+            Cmd::Apply(cmd) => cmd.run(),
+            Cmd::Library(cmd) => cmd.run(),
             Cmd::Profile(cmd) => cmd.run(),
+            Cmd::Config(cmd) => cmd.run(),
         }
     }
 }
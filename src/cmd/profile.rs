@@ -1,14 +1,18 @@
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use anyhow::{bail, Context, Result};
 
 extern crate directories;
 use directories::BaseDirs;
 
-use serde_json::{json, Value};
+use serde_json::Map;
 
-use crate::cmd::{Create, Delete, Edit, Flags, Init, List, Profile, ProfileCommand, Run};
+use crate::cmd::{
+    Create, Delete, Edit, Export, Flags, Import, Init, List, Profile, ProfileCommand, Run,
+};
+use crate::config::profile::{Locations, ProfileConfig, ProfileFlags, ResolvedFlags};
+use crate::config::resolve::resolve;
 
 fn get_or_create_profiles_dir() -> Result<PathBuf> {
     let base_dirs = BaseDirs::new().context("Could not get base directories")?;
@@ -37,96 +41,53 @@ pub fn get_profile_by_name(name: &str) -> Result<PathBuf> {
     Ok(profile_path)
 }
 
-pub fn get_profile_properties(
-    path: &PathBuf,
-) -> Result<(String, String, serde_json::Map<String, Value>)> {
-    let profile_str = fs::read_to_string(path)?;
+/// Load a profile and merge in the flags of the profiles it extends.
+pub fn get_profile_properties(path: &Path) -> Result<ProfileConfig> {
+    let mut profile = ProfileConfig::load(path)?;
+    inherit_parents(&mut profile)?;
+    Ok(profile)
+}
 
-    let profile: Value = serde_json::from_str(&profile_str)?;
+/// Walk the `extends` chain, nearest parent first, rejecting cycles.
+fn inherit_parents(profile: &mut ProfileConfig) -> Result<()> {
+    let mut chain = vec![profile.name.clone()];
+    let mut parent_name = profile.extends.clone();
+
+    while let Some(name) = parent_name {
+        if chain.contains(&name) {
+            bail!(
+                "Profile inheritance cycle: {} -> {}",
+                chain.join(" -> "),
+                name
+            );
+        }
 
-    let input = profile["input"].as_str().context("Profile has no input")?;
-    let output = profile["output"]
-        .as_str()
-        .context("Profile has no output")?;
-    let mut flags: serde_json::Map<String, Value> = profile["flags"]
-        .as_object()
-        .context("Profile has no flags")?
-        .clone();
+        let parent = ProfileConfig::load(&get_profile_by_name(&name)?)?;
+        profile.flags.inherit(&parent.flags)?;
 
-    check_or_add_all_flag(&mut flags);
+        chain.push(name);
+        parent_name = parent.extends;
+    }
 
-    Ok((input.to_string(), output.to_string(), flags))
+    Ok(())
 }
 
-fn check_or_add_all_flag(flags: &mut serde_json::Map<String, Value>) {
-    if !flags.contains_key("verbose") {
-        flags.insert("verbose".to_string(), serde_json::Value::Bool(false));
-    }
-    if !flags.contains_key("recursive") {
-        flags.insert("recursive".to_string(), serde_json::Value::Bool(false));
-    }
-    if !flags.contains_key("threads") {
-        let num_cpus: usize = num_cpus::get() - 2;
-        flags.insert(
-            "threads".to_string(),
-            serde_json::Value::Number(serde_json::Number::from(num_cpus)),
-        );
-    }
-    if !flags.contains_key("webhook") {
-        flags.insert(
-            "webhook".to_string(),
-            serde_json::Value::String("default".to_string()),
-        );
-    }
-    if !flags.contains_key("dry-run") {
-        flags.insert("dry-run".to_string(), serde_json::Value::Bool(false));
-    }
-    if !flags.contains_key("tv-template") {
-        flags.insert(
-            "tv-template".to_string(),
-            serde_json::Value::String("Series".to_string()),
-        );
-    }
-    if !flags.contains_key("movie-template") {
-        flags.insert(
-            "movie-template".to_string(),
-            serde_json::Value::String("Films".to_string()),
-        );
-    }
-    if !flags.contains_key("search") {
-        flags.insert("search".to_string(), serde_json::Value::Bool(false));
-    }
-    if !flags.contains_key("skip-subtitles") {
-        flags.insert("skip-subtitles".to_string(), serde_json::Value::Bool(false));
+/// Replace the home directory with `~` so the path still makes sense on another machine.
+fn portable_path(path: &Path) -> PathBuf {
+    let home = BaseDirs::new().map(|base_dirs| base_dirs.home_dir().to_path_buf());
+    match home.and_then(|home| path.strip_prefix(home).ok().map(Path::to_path_buf)) {
+        Some(rest) => PathBuf::from("~").join(rest),
+        None => path.to_path_buf(),
     }
 }
 
-fn get_default_flags() -> serde_json::Map<String, serde_json::Value> {
-    let mut flags = serde_json::Map::new();
-    flags.insert("verbose".to_string(), serde_json::Value::Bool(false));
-    flags.insert("recursive".to_string(), serde_json::Value::Bool(false));
-    let num_cpus: usize = num_cpus::get() - 2;
-    flags.insert(
-        "threads".to_string(),
-        serde_json::Value::Number(serde_json::Number::from(num_cpus)),
-    );
-    flags.insert(
-        "webhook".to_string(),
-        serde_json::Value::String("default".to_string()),
-    );
-    flags.insert("dry-run".to_string(), serde_json::Value::Bool(false));
-    flags.insert(
-        "tv-template".to_string(),
-        serde_json::Value::String("Series".to_string()),
-    );
-    flags.insert(
-        "movie-template".to_string(),
-        serde_json::Value::String("Films".to_string()),
-    );
-    flags.insert("search".to_string(), serde_json::Value::Bool(false));
-    flags.insert("skip-subtitles".to_string(), serde_json::Value::Bool(false));
-
-    flags
+/// Expand a leading `~` to the home directory of this machine.
+fn local_path(path: &Path) -> PathBuf {
+    let home = BaseDirs::new().map(|base_dirs| base_dirs.home_dir().to_path_buf());
+    match (path.strip_prefix("~"), home) {
+        (Ok(rest), Some(home)) => home.join(rest),
+        _ => path.to_path_buf(),
+    }
 }
 
 impl Run for Profile {
@@ -148,6 +109,8 @@ impl Run for ProfileCommand {
             ProfileCommand::Edit(cmd) => cmd.run(),
             ProfileCommand::Flags(cmd) => cmd.run(),
             ProfileCommand::Init(cmd) => cmd.run(),
+            ProfileCommand::Export(cmd) => cmd.run(),
+            ProfileCommand::Import(cmd) => cmd.run(),
         }
     }
 }
@@ -162,37 +125,20 @@ impl Run for Create {
             bail!("Profile with name {} already exists", self.name);
         }
 
-        let mut flags = get_default_flags();
+        let mut profile = ProfileConfig::new(&self.name, self.input.clone(), self.output.clone());
+        profile.movies_output = self.movies_output.clone();
+        profile.series_output = self.series_output.clone();
+        profile.description = self.description.clone();
+        profile.extends = self.extends.clone();
 
         if let Some(cmd_flags) = &self.flags {
             for flag in cmd_flags {
-                let mut parts = flag.splitn(2, '=');
-                let key = parts.next().context("Flag has no key")?.to_string();
-                let value = parts.next().context("Flag has no value")?.to_string();
-                // Attempt to parse the value as a bool or number, fallback to string
-                if let Ok(bool_value) = value.parse::<bool>() {
-                    flags.insert(key, serde_json::Value::Bool(bool_value));
-                } else if let Ok(number_value) = value.parse::<i64>() {
-                    flags.insert(
-                        key,
-                        serde_json::Value::Number(serde_json::Number::from(number_value)),
-                    );
-                } else {
-                    flags.insert(key, serde_json::Value::String(value));
-                }
+                profile.flags.set_from_str(flag)?;
             }
         }
 
-        let profile = json!({
-          "name": self.name,
-          "input": self.input,
-          "output": self.output,
-          "flags": flags,
-        });
-
-        let profile_str = serde_json::to_string_pretty(&profile)?;
-
-        fs::write(&profile_path, profile_str)?;
+        inherit_parents(&mut profile.clone())?;
+        profile.save(&profile_path)?;
 
         println!("Profile {:?} successfully created", self.name);
 
@@ -262,81 +208,138 @@ impl Edit {
     pub fn run(&mut self) -> Result<()> {
         let profile_path = get_profile_by_name(&self.name)?;
 
-        let mut profile_str = fs::read_to_string(&profile_path)?;
-
-        let mut profile: Value = serde_json::from_str(&profile_str)?;
+        let mut profile = ProfileConfig::load(&profile_path)?;
 
-        if self.key == "flags" && self.value == "reset" {
-            profile["flags"] = Value::Object(get_default_flags());
-            profile_str = serde_json::to_string_pretty(&profile)?;
-            fs::write(&profile_path, profile_str)?;
+        match self.key.as_str() {
+            "flags" if self.value == "reset" => {
+                profile.flags = ProfileFlags::default();
+                profile.save(&profile_path)?;
 
-            println!("Profile {:?} flags successfully reset", self.name);
+                println!("Profile {:?} flags successfully reset", self.name);
 
-            return Ok(());
+                return Ok(());
+            }
+            "flags" => profile.flags.set_from_str(&self.value)?,
+            key if Locations::KEYS.contains(&key) => profile.set_location(key, &self.value)?,
+            "description" => profile.description = Some(self.value.clone()),
+            "extends" if self.value.is_empty() => profile.extends = None,
+            "extends" => profile.extends = Some(self.value.clone()),
+            key => bail!(
+                "Unknown profile key {:?}, expected one of: flags, {}, description, extends",
+                key,
+                Locations::KEYS.join(", ")
+            ),
         }
 
-        if self.key == "flags" {
-            let mut flags = profile["flags"]
-                .as_object()
-                .context("Profile has no flags")?
-                .clone();
-
-            let mut parts = self.value.splitn(2, '=');
-            let key = parts.next().context("Flag has no key")?.to_string();
-            let value = parts.next().context("Flag has no value")?.to_string();
-
-            // Attempt to parse the value as a bool or number, fallback to string
-            if let Ok(bool_value) = value.parse::<bool>() {
-                flags.insert(key, serde_json::Value::Bool(bool_value));
-            } else if let Ok(number_value) = value.parse::<i64>() {
-                flags.insert(
-                    key,
-                    serde_json::Value::Number(serde_json::Number::from(number_value)),
-                );
-            } else {
-                flags.insert(key, serde_json::Value::String(value));
-            }
+        inherit_parents(&mut profile.clone())?;
+        profile.save(&profile_path)?;
 
-            profile["flags"] = Value::Object(flags);
+        println!("Profile {:?} successfully edited", self.name);
 
-            profile_str = serde_json::to_string_pretty(&profile)?;
+        Ok(())
+    }
+}
+
+impl Flags {
+    pub fn run(&mut self) -> Result<()> {
+        let profile_path = get_profile_by_name(&self.name)?;
 
-            fs::write(&profile_path, profile_str)?;
+        let profile = get_profile_properties(&profile_path)?;
 
-            println!("Profile {:?} successfully edited", self.name);
+        let resolved = resolve(Some(&profile), Map::new())?;
 
-            return Ok(());
+        for key in ResolvedFlags::keys() {
+            println!(
+                "{}: {} ({})",
+                key, resolved.values[&key], resolved.sources[&key]
+            );
         }
 
-        profile[self.key.clone()] = Value::String(self.value.clone());
+        Ok(())
+    }
+}
 
-        profile_str = serde_json::to_string_pretty(&profile)?;
+impl Run for Export {
+    fn run(&mut self) -> Result<()> {
+        let profile_path = get_profile_by_name(&self.name)?;
 
-        fs::write(&profile_path, profile_str)?;
+        let mut profile = ProfileConfig::load(&profile_path)?;
+        profile.map_paths(portable_path);
 
-        println!("Profile {:?} successfully edited", self.name);
+        let profile_str = serde_json::to_string_pretty(&profile)?;
+
+        match &self.file {
+            Some(file) => {
+                fs::write(file, profile_str)
+                    .with_context(|| format!("Could not write {:?}", file))?;
+                println!(
+                    "Profile {:?} successfully exported to {:?}",
+                    self.name, file
+                );
+            }
+            None => println!("{}", profile_str),
+        }
 
         Ok(())
     }
 }
 
-impl Flags {
-    pub fn run(&mut self) -> Result<()> {
-        let profile_path = get_profile_by_name(&self.name)?;
+impl Run for Import {
+    fn run(&mut self) -> Result<()> {
+        let profile_str = fs::read_to_string(&self.file)
+            .with_context(|| format!("Could not read {:?}", self.file))?;
+        let mut profile = ProfileConfig::from_value(serde_json::from_str(&profile_str)?)
+            .with_context(|| format!("Invalid profile {:?}", self.file))?;
 
-        let profile_str = fs::read_to_string(&profile_path)?;
+        if let Some(name) = &self.name {
+            profile.name = name.clone();
+        }
+        profile.map_paths(local_path);
+        if !self.input.is_empty() {
+            profile.inputs = self.input.clone();
+        }
+        if let Some(output) = &self.output {
+            profile.output = output.clone();
+        }
 
-        let profile: Value = serde_json::from_str(&profile_str)?;
+        for path in profile.directories() {
+            if !path.exists() {
+                println!(
+                    "Warning: directory {:?} does not exist on this machine, use --input/--output or profile edit to replace it",
+                    path
+                );
+            }
+        }
 
-        let flags = profile["flags"]
-            .as_object()
-            .context("Profile has no flags")?;
+        match &profile.extends {
+            Some(parent) if get_profile_by_name(parent).is_err() => println!(
+                "Warning: parent profile {:?} does not exist, import it before using this profile",
+                parent
+            ),
+            Some(_) => inherit_parents(&mut profile.clone())?,
+            None => {}
+        }
 
-        for (key, value) in flags {
-            println!("{}: {}", key, value);
+        let profile_path = get_or_create_profiles_dir()?.join(format!("{}.pms", profile.name));
+
+        if profile_path.exists() {
+            let existing = ProfileConfig::load(&profile_path)?;
+            if serde_json::to_value(&existing)? == serde_json::to_value(&profile)? {
+                println!("Profile {:?} is already up to date", profile.name);
+                return Ok(());
+            }
+            if !self.force {
+                bail!(
+                    "Profile {:?} already exists with different settings, use --force to overwrite it or --name to import it under another name",
+                    profile.name
+                );
+            }
         }
 
+        profile.save(&profile_path)?;
+
+        println!("Profile {:?} successfully imported", profile.name);
+
         Ok(())
     }
 }
@@ -1,86 +1,257 @@
 use core::time;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
-use anyhow::{bail, Ok, Result};
+use anyhow::{bail, Context, Ok, Result};
 
 use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
 use once_cell::sync::Lazy;
 use rayon::{prelude::*, ThreadPoolBuilder};
 
-use serde_json::json;
-
-use ureq;
-
-use crate::cmd::{profile, Run, Sort};
-use crate::episode::Episode;
-use crate::search::result::MediaResult;
-use crate::search::{self};
-use crate::subtitle::Subtitle;
-
-static MULTI_PROGRESS: Lazy<MultiProgress> = Lazy::new(|| MultiProgress::new());
+use serde_json::{json, Map, Value};
+
+use crate::archive::{self, Archive};
+use crate::cmd::{profile, OutputFormat, Run, Sort};
+use crate::config::resolve::resolve;
+use crate::convert::{self, SubtitleFormat};
+use crate::duplicate::{find_duplicates, trash_path, DuplicateGroup, KeepPolicy, MediaCopy};
+use crate::episode::{folder_season, Episode};
+use crate::extra::{self, ExtraKind, SamplePolicy};
+use crate::extract;
+use crate::language;
+use crate::plan::{Plan, PlannedMove};
+use crate::probe;
+use crate::report::{write_report, Action, FileType, ReportRow, Skip};
+use crate::search::result::{MediaResult, SearchMatch};
+use crate::search::{self, strings::sanitize_filename};
+use crate::sidecar::{self, JunkPolicy, SidecarFile};
+use crate::subtitle::{match_subtitles, Subtitle, SubtitleLayout};
+
+static MULTI_PROGRESS: Lazy<MultiProgress> = Lazy::new(MultiProgress::new);
 
 impl Run for Sort {
     fn run(&mut self) -> Result<()> {
         self.setup_profile()?;
         self.validate_io()?;
+        if self.format == OutputFormat::Json && !self.dry_run {
+            bail!("--format json is only supported with --dry-run");
+        }
+        if !probe::is_available() {
+            self.info("Warning: ffprobe was not found, medias will be classified from their file names only");
+        }
         let global_timer = Instant::now();
         self.sort_medias_threaded()?;
-        println!(
+        self.info(&format!(
             "\nMedias sorted successfully in {:?}",
             global_timer.elapsed()
-        );
+        ));
         Ok(())
     }
 }
 
 impl Sort {
+    /// Fill every setting from the defaults, global config, profile and environment,
+    /// keeping the flags that were given on the command line.
     fn setup_profile(&mut self) -> Result<()> {
-        if let Some(profile_name) = &self.profile {
-            let profile = profile::get_profile_by_name(profile_name)?;
-            let (input, output, flags) = profile::get_profile_properties(&profile)?;
-
-            self.input = Some(PathBuf::from(input));
-            self.output = Some(PathBuf::from(output));
-            self.verbose = flags["verbose"].as_bool().unwrap_or(false);
-            self.threads = flags["threads"].as_u64().map(|n| n as usize);
-            self.recursive = flags["recursive"].as_bool().unwrap_or(false);
-            self.webhook = flags["webhook"].as_str().map(|s| s.to_string());
-            self.dry_run = flags["dry-run"].as_bool().unwrap_or(false);
-            self.tv_template = flags["tv-template"].as_str().map(|s| s.to_string());
-            self.movie_template = flags["movie-template"].as_str().map(|s| s.to_string());
-            self.search = flags["search"].as_bool().unwrap_or(false);
-            self.skip_subtitles = flags["skip-subtitles"].as_bool().unwrap_or(false);
+        let profile = match &self.profile {
+            Some(profile_name) => {
+                let profile = profile::get_profile_by_name(profile_name)?;
+                Some(profile::get_profile_properties(&profile)?)
+            }
+            None => None,
+        };
+
+        let resolved = resolve(profile.as_ref(), self.cli_settings())?;
+        let locations = resolved.locations;
+        let flags = resolved.flags;
+
+        self.input = locations.inputs;
+        self.output = locations.output;
+        self.movies_output = locations.movies_output;
+        self.series_output = locations.series_output;
+        self.routes = locations.routes;
+        self.verbose = flags.verbose;
+        self.threads = Some(flags.threads);
+        self.recursive = flags.recursive;
+        self.webhook = Some(flags.webhook);
+        self.dry_run = flags.dry_run;
+        self.tv_template = Some(flags.tv_template);
+        self.movie_template = Some(flags.movie_template);
+        self.search = flags.search;
+        self.skip_subtitles = flags.skip_subtitles;
+        self.keep = Some(flags.keep);
+        self.trash = flags.trash;
+        self.upgrade = flags.upgrade;
+        self.quality = flags.quality;
+        self.subtitle_layout = Some(flags.subtitle_layout);
+        self.subtitle_format = flags.subtitle_format;
+        self.utf8_subtitles = flags.utf8_subtitles;
+        self.keep_original_subtitles = flags.keep_original_subtitles;
+        self.extract_subtitles = flags.extract_subtitles;
+        self.skip_sidecars = flags.skip_sidecars;
+        self.junk = Some(flags.junk);
+        self.samples = Some(flags.samples);
+        self.sample_size = Some(flags.sample_size);
+        self.sample_duration = Some(flags.sample_duration);
+        self.extract_archives = flags.extract_archives;
+        self.delete_archives = flags.delete_archives;
+        if self.in_place {
+            self.keep_destinations_in_output();
+        }
+
+        for (key, source) in &resolved.sources {
+            self.verbose(&format!("{} = {} ({})", key, resolved.values[key], source));
         }
         Ok(())
     }
 
+    /// Drop the movies output, series output and rules leading outside of the output
+    /// directory, so that a library fix never moves a file out of the library.
+    fn keep_destinations_in_output(&mut self) {
+        let Some(root) = self.output.clone() else {
+            return;
+        };
+        let inside = |path: &PathBuf| path.starts_with(&root);
+        self.movies_output = self.movies_output.take().filter(inside);
+        self.series_output = self.series_output.take().filter(inside);
+        self.routes.retain(|route| route.output.iter().all(inside));
+    }
+
+    /// Settings explicitly given on the command line.
+    fn cli_settings(&self) -> Map<String, Value> {
+        let mut cli = Map::new();
+        if !self.input.is_empty() {
+            cli.insert("inputs".into(), json!(self.input));
+        }
+        if let Some(output) = &self.output {
+            cli.insert("output".into(), json!(output));
+        }
+        if let Some(movies_output) = &self.movies_output {
+            cli.insert("movies-output".into(), json!(movies_output));
+        }
+        if let Some(series_output) = &self.series_output {
+            cli.insert("series-output".into(), json!(series_output));
+        }
+        if let Some(threads) = self.threads {
+            cli.insert("threads".into(), json!(threads));
+        }
+        if let Some(webhook) = &self.webhook {
+            cli.insert("webhook".into(), json!(webhook));
+        }
+        if let Some(tv_template) = &self.tv_template {
+            cli.insert("tv-template".into(), json!(tv_template));
+        }
+        if let Some(movie_template) = &self.movie_template {
+            cli.insert("movie-template".into(), json!(movie_template));
+        }
+        if let Some(keep) = &self.keep {
+            cli.insert("keep".into(), json!(keep));
+        }
+        if let Some(subtitle_layout) = &self.subtitle_layout {
+            cli.insert("subtitle-layout".into(), json!(subtitle_layout));
+        }
+        if let Some(subtitle_format) = &self.subtitle_format {
+            cli.insert("subtitle-format".into(), json!(subtitle_format));
+        }
+        if let Some(junk) = &self.junk {
+            cli.insert("junk".into(), json!(junk));
+        }
+        if let Some(samples) = &self.samples {
+            cli.insert("samples".into(), json!(samples));
+        }
+        if let Some(sample_size) = self.sample_size {
+            cli.insert("sample-size".into(), json!(sample_size));
+        }
+        if let Some(sample_duration) = self.sample_duration {
+            cli.insert("sample-duration".into(), json!(sample_duration));
+        }
+        if let Some(trash) = &self.trash {
+            cli.insert("trash".into(), json!(trash));
+        }
+        // `--no-*` flags set false, so that they override a layer below
+        for (key, enabled, disabled) in [
+            ("verbose", self.verbose, self.no_verbose),
+            ("recursive", self.recursive, self.no_recursive),
+            ("dry-run", self.dry_run, self.no_dry_run),
+            ("search", self.search, self.no_search),
+            (
+                "skip-subtitles",
+                self.skip_subtitles,
+                self.no_skip_subtitles,
+            ),
+            ("upgrade", self.upgrade, self.no_upgrade),
+            (
+                "utf8-subtitles",
+                self.utf8_subtitles,
+                self.no_utf8_subtitles,
+            ),
+            (
+                "keep-original-subtitles",
+                self.keep_original_subtitles,
+                self.no_keep_original_subtitles,
+            ),
+            (
+                "extract-subtitles",
+                self.extract_subtitles,
+                self.no_extract_subtitles,
+            ),
+            ("skip-sidecars", self.skip_sidecars, self.no_skip_sidecars),
+            (
+                "extract-archives",
+                self.extract_archives,
+                self.no_extract_archives,
+            ),
+            (
+                "delete-archives",
+                self.delete_archives,
+                self.no_delete_archives,
+            ),
+        ] {
+            if enabled || disabled {
+                cli.insert(key.into(), Value::Bool(enabled));
+            }
+        }
+        cli
+    }
+
     fn validate_io(&self) -> Result<()> {
-        if self.input.is_none() {
+        if self.input.is_empty() {
             bail!("Input directory is required");
         }
 
-        if self.output.is_none() {
+        if self.output.is_none() && (self.movies_output.is_none() || self.series_output.is_none()) {
             bail!("Output directory is required");
         }
 
-        if !self.input.clone().unwrap().exists() {
-            bail!("Input directory does not exist");
+        for input in &self.input {
+            if !input.exists() {
+                bail!("Input directory does not exist: {:?}", input);
+            }
         }
         Ok(())
     }
 
+    /// Print a message, to stderr when stdout is kept for the JSON plan.
+    fn info(&self, message: &str) {
+        if self.format == OutputFormat::Json {
+            eprintln!("{}", message);
+        } else {
+            println!("{}", message);
+        }
+    }
+
     fn verbose(&self, message: &str) {
         if self.verbose {
-            println!("{}", message);
+            self.info(message);
         }
     }
 
     fn search_database(&self, episode: &mut Episode) -> Result<()> {
         let mut results;
+        let mut provider = "TMDB";
         let name: String = episode.name.clone();
         if episode.is_movie {
             results = search::search_tmdb::search_movie_db(
@@ -95,7 +266,9 @@ impl Sort {
                 None,
                 search::result::MediaType::Series,
             )?;
+            provider = "TVMaze";
             if results.is_empty() {
+                provider = "TMDB";
                 results = search::search_tmdb::search_movie_db(
                     &episode.name,
                     None,
@@ -105,11 +278,15 @@ impl Sort {
             }
         }
         let closest_result: Option<MediaResult> = search::result::get_highest_accuracy(results);
-        if closest_result.is_some() {
-            let best_result = closest_result.unwrap();
+        if let Some(best_result) = closest_result {
             let sanitized_name = sanitize_filename(&best_result.title);
             episode.set_name(sanitized_name.as_str());
             episode.year = best_result.year.parse::<u32>().ok();
+            episode.genres = best_result.genres;
+            episode.search_match = Some(SearchMatch {
+                provider: provider.to_string(),
+                confidence: best_result.accuracy,
+            });
         } else {
             episode.set_name(&name);
         }
@@ -118,22 +295,12 @@ impl Sort {
 
     fn register_subtitles(
         &self,
-        path: &PathBuf,
+        path: &Path,
         subtitles: &mut Vec<Subtitle>,
         start_instant: &Instant,
     ) -> Result<()> {
-        let mut subtitle: Subtitle = Subtitle::new(path.clone());
-
-        // Check for "Title:" and its value in the file
-        if let std::result::Result::Ok(content) = fs::read_to_string(path) {
-            for line in content.lines() {
-                if line.starts_with("Title:") {
-                    let language = line.trim_start_matches("Title:").trim().to_string();
-                    subtitle.language = Some(language);
-                    break;
-                }
-            }
-        }
+        let mut subtitle: Subtitle = Subtitle::new(path.to_path_buf())?;
+        subtitle.set_language(language::detect(path));
 
         subtitles.push(subtitle.clone());
         self.verbose(&format!(
@@ -147,13 +314,39 @@ impl Sort {
 
     fn register_media(
         &self,
-        path: &PathBuf,
+        path: &Path,
         episodes: &mut Vec<Episode>,
         start_instant: &Instant,
     ) -> Result<()> {
-        let mut episode: Episode = Episode::new(path);
+        let input = self
+            .input
+            .iter()
+            .filter(|input| path.starts_with(input))
+            .max_by_key(|input| input.components().count());
+        let mut episode: Episode = Episode::new(path, input.map(PathBuf::as_path))?;
+        // Sorted files keep their season in the folder name only
+        if self.in_place && episode.season == 0 && episode.episode > 0 {
+            if let Some(season) = folder_season(path) {
+                episode.season = season;
+                episode.special = season == 0;
+            }
+        }
+        // A sorted special keeps the title it was named with, `Show - E01 - Title.mkv`
+        if self.in_place && episode.special {
+            let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+            let prefix = format!("{} - E{:02} - ", episode.name, episode.episode);
+            if let Some(title) = stem.strip_prefix(&prefix).filter(|title| !title.is_empty()) {
+                episode.title = Some(title.to_string());
+            }
+        }
         if self.search {
             self.search_database(&mut episode)?;
+            if episode.special {
+                match search::search_tvmaze::search_specials(&episode.name) {
+                    std::result::Result::Ok(specials) => episode.name_special(&specials),
+                    Err(e) => self.verbose(&format!("Could not list the specials: {:#}", e)),
+                }
+            }
         }
 
         episodes.push(episode.clone());
@@ -163,9 +356,36 @@ impl Sort {
             episode.filename_clean,
             start_instant.elapsed()
         ));
+        for conflict in &episode.probe_conflicts {
+            self.verbose(&format!("  {}", conflict));
+        }
+        if self.verbose {
+            self.explain_routes(&episode);
+        }
         Ok(())
     }
 
+    /// Print how each routing rule was evaluated for a media.
+    fn explain_routes(&self, episode: &Episode) {
+        for (i, route) in self.routes.iter().enumerate() {
+            match route.check(episode) {
+                std::result::Result::Ok(()) => {
+                    self.info(&format!("  rule #{} {} matched", i + 1, route.label()));
+                    return;
+                }
+                Err(reason) => self.info(&format!(
+                    "  rule #{} {} skipped: {}",
+                    i + 1,
+                    route.label(),
+                    reason
+                )),
+            }
+        }
+        if !self.routes.is_empty() {
+            self.info("  no rule matched, using the default output");
+        }
+    }
+
     fn check_media_status(&self, episodes: &Mutex<Vec<Episode>>) -> Result<()> {
         if episodes.lock().unwrap().is_empty() {
             bail!("No media files found in the input directory");
@@ -193,36 +413,178 @@ impl Sort {
         Ok(files)
     }
 
-    fn get_medias_from_input(&self, skip_subtitle: bool) -> Result<(Vec<Episode>, Vec<Subtitle>)> {
+    /// Parse every media and subtitle of the inputs. Files that cannot be parsed are
+    /// returned as failures instead of stopping the run.
+    fn get_medias_from_input(&self, skip_subtitle: bool) -> Result<Inputs> {
         let timer = Instant::now();
-        let input_path = self.input.clone().unwrap();
         let episodes: Mutex<Vec<Episode>> = Vec::new().into();
         let subtitles: Mutex<Vec<Subtitle>> = Vec::new().into();
-        let media_paths = self.collect_files(input_path.as_path(), self.recursive)?;
+        let mut failures = Vec::new();
+        let mut others = Vec::new();
+        let mut parse_times = HashMap::new();
+        let mut media_paths = Vec::new();
+        for input_path in &self.input {
+            media_paths.extend(self.collect_files(input_path, self.recursive)?);
+        }
+        let archives = self.extract_archives(&mut media_paths, &mut failures);
 
         for path in media_paths {
-            if !self.is_media(&path) && !self.is_subtitles(&path) {
+            let file_timer = Instant::now();
+            let registered = if !self.is_media(&path) && !self.is_subtitles(&path) {
+                others.push(path);
                 continue;
             } else if self.is_media(&path) {
-                self.register_media(&path, &mut episodes.lock().unwrap(), &timer)?;
-            } else if skip_subtitle == false && self.is_subtitles(&path) {
-                self.register_subtitles(&path, &mut subtitles.lock().unwrap(), &timer)?;
+                self.register_media(&path, &mut episodes.lock().unwrap(), &timer)
+            } else if !skip_subtitle && self.is_subtitles(&path) {
+                self.register_subtitles(&path, &mut subtitles.lock().unwrap(), &timer)
+            } else {
+                continue;
+            };
+
+            match registered {
+                std::result::Result::Ok(()) => {
+                    parse_times.insert(path, file_timer.elapsed());
+                }
+                Err(e) => {
+                    self.verbose(&format!("Could not parse {:?}: {:#}", path, e));
+                    failures.push(ReportRow::from_error(&path, e, file_timer.elapsed()));
+                }
             }
         }
-        self.check_media_status(&episodes)?;
+        if failures.is_empty() {
+            self.check_media_status(&episodes)?;
+        }
 
         self.verbose(&format!(
             "Found {} media files in {:?}",
             episodes.lock().unwrap().len(),
             timer.elapsed()
         ));
-        Ok((
-            episodes.into_inner().unwrap(),
-            subtitles.into_inner().unwrap(),
-        ))
+        Ok(Inputs {
+            episodes: episodes.into_inner().unwrap(),
+            subtitles: subtitles.into_inner().unwrap(),
+            failures,
+            others,
+            archives,
+            parse_times,
+        })
     }
 
-    fn is_media(&self, path: &PathBuf) -> bool {
+    /// Extract the archives of the inputs to a temporary folder, and replace their volumes
+    /// with the extracted files. Archives that cannot be extracted are reported and left
+    /// in the input. A plan only moves files, so it leaves the archives alone.
+    fn extract_archives(
+        &self,
+        files: &mut Vec<PathBuf>,
+        failures: &mut Vec<ReportRow>,
+    ) -> Vec<(Archive, PathBuf)> {
+        let plan_only = self.dry_run && self.format == OutputFormat::Json;
+        if !self.extract_archives || self.in_place || plan_only {
+            return Vec::new();
+        }
+        let archives = archive::find_archives(files);
+        let volumes: HashSet<&PathBuf> = archives
+            .iter()
+            .flat_map(|archive| &archive.volumes)
+            .collect();
+        let mut extracted_files = Vec::new();
+        let mut extracted = Vec::new();
+        for (index, archive) in archives.iter().enumerate() {
+            let timer = Instant::now();
+            let dir = extraction_dir()
+                .join(index.to_string())
+                .join(archive.name());
+            let result =
+                archive::extract(archive, &dir).and_then(|()| self.collect_files(&dir, true));
+            match result {
+                std::result::Result::Ok(content) => {
+                    self.verbose(&format!(
+                        "Extracted {} files from {:?}",
+                        content.len(),
+                        archive.path
+                    ));
+                    extracted_files.extend(content);
+                    extracted.push((archive.clone(), dir));
+                }
+                Err(e) => {
+                    self.verbose(&format!("Could not extract {:?}: {:#}", archive.path, e));
+                    failures.push(
+                        ReportRow::from_file(
+                            &archive.path,
+                            FileType::Archive,
+                            Action::Skipped,
+                            timer.elapsed(),
+                        )
+                        .with_reason(&format!("{:#}", e)),
+                    );
+                }
+            }
+        }
+        files.retain(|path| !volumes.contains(path));
+        files.extend(extracted_files);
+        extracted
+    }
+
+    /// Archive sets whose medias were all sorted: at least one moved, and none failed or
+    /// in conflict. During a dry run, the medias a real run would move count as sorted.
+    fn archives_to_delete<'a>(
+        &self,
+        archives: &'a [(Archive, PathBuf)],
+        rows: &[ReportRow],
+    ) -> Vec<&'a Archive> {
+        if !self.delete_archives {
+            return Vec::new();
+        }
+        archives
+            .iter()
+            .filter(|(_, dir)| {
+                let content: Vec<&ReportRow> = rows
+                    .iter()
+                    .filter(|row| row.source.starts_with(dir))
+                    .filter(|row| {
+                        matches!(
+                            row.media_type,
+                            None | Some(FileType::Movie | FileType::Series)
+                        )
+                    })
+                    .collect();
+                let sorted = |row: &&ReportRow| {
+                    matches!(row.action, Action::Moved | Action::Upgraded)
+                        || row.skip == Skip::DryRun
+                };
+                content.iter().any(sorted)
+                    && !content
+                        .iter()
+                        .any(|row| matches!(row.action, Action::Error | Action::Conflict))
+            })
+            .map(|(archive, _)| archive)
+            .collect()
+    }
+
+    fn delete_archives(
+        &self,
+        archives: &[(Archive, PathBuf)],
+        rows: &[ReportRow],
+    ) -> Vec<ReportRow> {
+        self.archives_to_delete(archives, rows)
+            .into_iter()
+            .flat_map(|archive| &archive.volumes)
+            .map(|path| {
+                let timer = Instant::now();
+                match fs::remove_file(path) {
+                    std::result::Result::Ok(()) => ReportRow::from_file(
+                        path,
+                        FileType::Archive,
+                        Action::Deleted,
+                        timer.elapsed(),
+                    ),
+                    Err(e) => ReportRow::from_error(path, e, timer.elapsed()),
+                }
+            })
+            .collect()
+    }
+
+    fn is_media(&self, path: &Path) -> bool {
         static MEDIA_EXTENSIONS: Lazy<HashSet<&str>> = Lazy::new(|| {
             ["mp4", "mkv", "avi", "mov", "flv", "wmv", "webm"]
                 .iter()
@@ -236,9 +598,13 @@ impl Sort {
             .unwrap_or(false)
     }
 
-    fn is_subtitles(&self, path: &PathBuf) -> bool {
-        static SUBTITLES_EXTENSIONS: Lazy<HashSet<&str>> =
-            Lazy::new(|| ["srt", "sub", "vtt", "ass"].iter().cloned().collect());
+    fn is_subtitles(&self, path: &Path) -> bool {
+        static SUBTITLES_EXTENSIONS: Lazy<HashSet<&str>> = Lazy::new(|| {
+            ["srt", "sub", "vtt", "ass", "ssa"]
+                .iter()
+                .cloned()
+                .collect()
+        });
 
         path.extension()
             .and_then(|ext| ext.to_str())
@@ -247,7 +613,7 @@ impl Sort {
     }
 
     fn setup_thread_pool(&self) -> Result<()> {
-        let max_cpu_count: usize = num_cpus::get() - 1;
+        let max_cpu_count: usize = num_cpus::get().saturating_sub(1).max(1);
         let num_threads: usize = self.threads.unwrap_or(max_cpu_count).min(max_cpu_count);
 
         if num_threads == 0 {
@@ -263,123 +629,916 @@ impl Sort {
         Ok(())
     }
 
-    fn move_episodes(&self, episodes: &Vec<Episode>) -> Result<()> {
+    /// Move every media, with what happened to each one.
+    fn move_episodes(&self, episodes: &Vec<Episode>) -> Vec<ReportRow> {
         let timer = Instant::now();
         let dir_set: Arc<Mutex<HashSet<PathBuf>>> = Arc::new(Mutex::new(HashSet::new()));
         let pb = get_progress_bar(episodes.len());
         pb.set_message("Moving files");
 
-        episodes.par_iter().try_for_each(|episode| -> Result<()> {
-            let dest_dir: PathBuf = self.find_or_create_dir(&episode, dir_set.clone())?;
-            pb.set_message(format!("Moving files - {}", episode.name));
-            self.move_media(&episode, &dest_dir, &pb)?;
-            pb.inc(1);
-            Ok(())
-        })?;
+        let rows: Vec<ReportRow> = episodes
+            .par_iter()
+            .map(|episode| {
+                let file_timer = Instant::now();
+                let moved =
+                    self.find_or_create_dir(episode, dir_set.clone())
+                        .and_then(|dest_dir| {
+                            pb.set_message(format!("Moving files - {}", episode.name));
+                            self.move_media(episode, &dest_dir, &pb)
+                        });
+                pb.inc(1);
+                match moved {
+                    std::result::Result::Ok((action, to_path, reason)) => {
+                        let skip = if to_path == episode.full_path {
+                            Skip::InPlace
+                        } else {
+                            Skip::No
+                        };
+                        let row = ReportRow::from_episode(
+                            episode,
+                            action,
+                            Some(to_path),
+                            file_timer.elapsed(),
+                        )
+                        .with_skip(skip);
+                        match reason {
+                            Some(reason) => row.with_reason(&reason),
+                            None => row,
+                        }
+                    }
+                    Err(e) => ReportRow::from_error(&episode.full_path, e, file_timer.elapsed()),
+                }
+            })
+            .collect();
 
         pb.finish_with_message("Moving completed");
 
         self.verbose(&format!(
             "Moved {} media files in {:?}",
-            episodes.len(),
+            count_action(&rows, Action::Moved),
             timer.elapsed()
         ));
 
-        Ok(())
+        rows
     }
 
-    fn move_subtitles(&self, subtitles: &Vec<Subtitle>) -> Result<()> {
+    /// Move every subtitle, with what happened to each one.
+    fn move_subtitles(&self, subtitles: &Vec<Subtitle>) -> Vec<ReportRow> {
         let timer = Instant::now();
         let dir_set: Arc<Mutex<HashSet<PathBuf>>> = Arc::new(Mutex::new(HashSet::new()));
         let pb = get_progress_bar(subtitles.len());
         pb.set_message("Moving subtitles");
 
-        subtitles
+        let rows: Vec<ReportRow> = subtitles
             .par_iter()
-            .try_for_each(|subtitle| -> Result<()> {
-                let dest_dir: PathBuf =
+            .map(|subtitle| {
+                let file_timer = Instant::now();
+                let moved = (|| -> Result<(Action, PathBuf)> {
                     self.find_or_create_dir(&subtitle.episode, dir_set.clone())?;
-                let subtitle_dir = dest_dir.join("Subtitles");
-                if !subtitle_dir.exists() {
-                    fs::create_dir_all(&subtitle_dir)?;
-                }
-                pb.set_message(format!("Moving subtitles - {}", subtitle.episode.name));
-                let from_path = subtitle.full_path.clone();
-                let to_path = subtitle_dir.join(self.get_new_filename(&subtitle.episode));
-                // Place lang before extension (e.g., .{lang}.{ext})
-                let lang = subtitle
-                    .language
-                    .clone()
-                    .unwrap_or_else(|| "Unknown".to_string());
-                let ext = subtitle.full_path.extension().unwrap().to_str().unwrap();
-                let to_path = to_path.with_extension(format!("{}.{}", lang, ext));
-
-                self.validate_move_paths(&from_path, &to_path)?;
-                self.execute_file_move(&from_path, &to_path)?;
+                    pb.set_message(format!("Moving subtitles - {}", subtitle.episode.name));
+                    let from_path = subtitle.full_path.clone();
+                    let to_path = self.get_subtitle_destination(subtitle);
+                    if let Some(subtitle_dir) = to_path.parent() {
+                        fs::create_dir_all(subtitle_dir)?;
+                    }
+
+                    if from_path == to_path {
+                        return Ok((Action::Skipped, to_path));
+                    }
+                    if !self.validate_move_paths(&from_path, &to_path)? {
+                        return Ok((Action::Conflict, to_path));
+                    }
+                    self.move_subtitle(subtitle, &to_path)?;
+                    Ok((Action::Moved, to_path))
+                })();
                 pb.inc(1);
-                Ok(())
-            })?;
+                match moved {
+                    std::result::Result::Ok((Action::Skipped, to_path)) => {
+                        ReportRow::from_subtitle(
+                            subtitle,
+                            Action::Skipped,
+                            Some(to_path),
+                            file_timer.elapsed(),
+                        )
+                        .with_reason("already in place")
+                        .with_skip(Skip::InPlace)
+                    }
+                    std::result::Result::Ok((action, to_path)) => ReportRow::from_subtitle(
+                        subtitle,
+                        action,
+                        Some(to_path),
+                        file_timer.elapsed(),
+                    ),
+                    Err(e) => ReportRow::from_error(&subtitle.full_path, e, file_timer.elapsed()),
+                }
+            })
+            .collect();
 
         pb.finish_with_message("Moving completed");
 
         self.verbose(&format!(
             "Moved {} subtitle files in {:?}",
-            subtitles.len(),
+            count_action(&rows, Action::Moved),
             timer.elapsed()
         ));
 
-        Ok(())
+        rows
     }
 
-    fn check_subtitles_names(
+    /// Move a subtitle, converting it to UTF-8 or to another format on the way when asked.
+    /// A converted subtitle is written to `to_path`, then its original is removed, or moved
+    /// next to it with its own extension.
+    fn move_subtitle(&self, subtitle: &Subtitle, to_path: &Path) -> Result<()> {
+        let from_path = &subtitle.full_path;
+        let converted = if self.utf8_subtitles || subtitle.format.is_some() {
+            convert::convert(from_path, subtitle.format)?
+        } else {
+            None
+        };
+        let Some(content) = converted else {
+            return move_file(from_path, to_path);
+        };
+
+        // Check where the original goes before writing anything, so that a collision
+        // leaves both files untouched
+        let original = from_path.extension().unwrap_or_default();
+        let original_path = (self.keep_original_subtitles && subtitle.format.is_some())
+            .then(|| to_path.with_extension(original));
+        if let Some(original_path) = &original_path {
+            if original_path == to_path || (original_path != from_path && original_path.exists()) {
+                bail!(
+                    "Cannot keep the original subtitle, {:?} exists",
+                    original_path
+                );
+            }
+        }
+
+        fs::write(to_path, content)?;
+        match original_path {
+            Some(original_path) if original_path == *from_path => Ok(()),
+            Some(original_path) => move_file(from_path, &original_path),
+            None => Ok(fs::remove_file(from_path)?),
+        }
+    }
+
+    /// Pick the subtitles converted to `--subtitle-format`, before their names are numbered
+    /// since the extension changes.
+    fn plan_conversions(&self, subtitles: &mut [Subtitle]) {
+        for subtitle in subtitles {
+            subtitle.format = self.conversion(&subtitle.full_path);
+        }
+    }
+
+    fn conversion(&self, path: &Path) -> Option<SubtitleFormat> {
+        self.subtitle_format
+            .filter(|&format| convert::can_convert(path, format))
+    }
+
+    /// Text subtitle streams of the medias to extract as sidecars, at most one per
+    /// language and kind, and none for a language that already has a sidecar.
+    fn embedded_subtitles(
         &self,
-        subtitles: &mut Vec<Subtitle>,
-        episodes: &Vec<Episode>,
-    ) -> Result<()> {
-        let episode_map: HashMap<_, _> = episodes.iter().map(|e| (&e.filename_clean, e)).collect();
+        episodes: &[Episode],
+        subtitles: &[Subtitle],
+    ) -> Vec<(Subtitle, i64)> {
+        let kind = |subtitle: &Subtitle| (subtitle.language.clone(), subtitle.forced, subtitle.sdh);
+        let mut embedded = Vec::new();
+
+        for episode in episodes {
+            let Some(info) = &episode.media_info else {
+                continue;
+            };
+            let mut kinds: Vec<_> = subtitles
+                .iter()
+                .filter(|subtitle| subtitle.episode.full_path == episode.full_path)
+                .map(kind)
+                .collect();
+
+            for track in &info.subtitle_tracks {
+                let Some(mut sidecar) = Subtitle::embedded(episode, track) else {
+                    continue;
+                };
+                sidecar.format = self.conversion(&sidecar.full_path);
+                if kinds.contains(&kind(&sidecar)) || self.has_sidecar(&sidecar) {
+                    self.verbose(&format!(
+                        "Not extracting stream {} of {:?}, its language has a subtitle",
+                        track.index, episode.full_path
+                    ));
+                    continue;
+                }
+                kinds.push(kind(&sidecar));
+                embedded.push((sidecar, track.index));
+            }
+        }
+
+        embedded
+    }
+
+    /// Whether a subtitle of the same language and kind is already next to the media.
+    fn has_sidecar(&self, sidecar: &Subtitle) -> bool {
+        let destination = self.get_subtitle_destination(sidecar);
+        let media = media_filename(&sidecar.episode);
+        let base = format!("{}.", Path::new(&media).with_extension("").display());
+        let Some(entries) = destination.parent().and_then(|dir| fs::read_dir(dir).ok()) else {
+            return false;
+        };
+
+        entries.flatten().any(|entry| {
+            let path = entry.path();
+            let name = entry.file_name().to_string_lossy().to_string();
+            let found = language::from_filename(&path);
+            name.starts_with(&base)
+                && self.is_subtitles(&path)
+                && (found.code, found.forced, found.sdh)
+                    == (sidecar.language.clone(), sidecar.forced, sidecar.sdh)
+        })
+    }
+
+    /// Write the embedded subtitles of the medias that were sorted next to them.
+    fn extract_embedded(&self, embedded: &[(Subtitle, i64)], rows: &[ReportRow]) -> Vec<ReportRow> {
+        if embedded.is_empty() {
+            return Vec::new();
+        }
+        if !extract::is_available() {
+            self.info("\nffmpeg is not installed, the embedded subtitles were not extracted");
+            return Vec::new();
+        }
 
-        for subtitle in subtitles.iter_mut() {
-            if let Some(episode) = episode_map.get(&subtitle.filename_clean) {
-                subtitle.set_episode((*episode).clone());
+        let sorted = sorted_sources(rows);
+        let mut extracted = Vec::new();
+        for (sidecar, index) in embedded {
+            if !sorted.contains(sidecar.episode.full_path.as_path()) {
+                continue;
             }
+            let timer = Instant::now();
+            let media = self.get_destination(&sidecar.episode);
+            let to_path = self.get_subtitle_destination(sidecar);
+            let written = (|| -> Result<()> {
+                if let Some(dir) = to_path.parent() {
+                    fs::create_dir_all(dir)?;
+                }
+                extract::extract_subtitle(&media, *index, &to_path)
+            })();
+
+            extracted.push(match written {
+                std::result::Result::Ok(()) => ReportRow {
+                    source: media,
+                    ..ReportRow::from_subtitle(
+                        sidecar,
+                        Action::Extracted,
+                        Some(to_path),
+                        timer.elapsed(),
+                    )
+                    .with_reason(&format!("stream {}", index))
+                },
+                Err(e) => ReportRow::from_error(&media, e, timer.elapsed()),
+            });
         }
 
-        Ok(())
+        self.verbose(&format!(
+            "Extracted {} embedded subtitles",
+            count_action(&extracted, Action::Extracted)
+        ));
+        extracted
     }
 
-    fn sort_medias_threaded(&self) -> Result<()> {
+    /// Pair the other files of the inputs with the media or `.sub` subtitle sharing their
+    /// base name, and pick the junk among the rest.
+    fn find_sidecars(
+        &self,
+        others: &[PathBuf],
+        episodes: &[Episode],
+        subtitles: &[Subtitle],
+    ) -> (Vec<SidecarFile>, Vec<PathBuf>) {
+        let layout = self.subtitle_layout();
+        let owners: Vec<(&Path, PathBuf, &Episode)> = episodes
+            .iter()
+            .map(|episode| {
+                let path = PathBuf::from(media_filename(episode));
+                (episode.full_path.as_path(), path, episode)
+            })
+            .chain(subtitles.iter().map(|subtitle| {
+                let path = subtitle_path(subtitle, layout);
+                (subtitle.full_path.as_path(), path, &subtitle.episode)
+            }))
+            .collect();
+
+        let mut sidecars = Vec::new();
+        let mut junk = Vec::new();
+        for path in others {
+            // `Movie.Part2.nfo` belongs to `Movie.Part2.mkv` rather than `Movie.mkv`
+            let sidecar = owners
+                .iter()
+                .filter_map(|(owner, owner_path, episode)| {
+                    SidecarFile::new(path, owner, owner_path, episode)
+                })
+                .max_by_key(|sidecar| sidecar.owner.as_os_str().len());
+            match sidecar {
+                Some(sidecar) if !self.skip_sidecars => sidecars.push(sidecar),
+                Some(_) => {}
+                None if sidecar::is_junk(path) && !self.in_place => junk.push(path.clone()),
+                None => self.verbose(&format!("Ignoring {:?}", path)),
+            }
+        }
+
+        (sidecars, junk)
+    }
+
+    fn get_sidecar_destination(&self, sidecar: &SidecarFile) -> PathBuf {
+        self.get_media_dir(&sidecar.episode).join(&sidecar.path)
+    }
+
+    /// Move the sidecars whose media or subtitle was sorted, renamed after it.
+    fn move_sidecars(&self, sidecars: &[SidecarFile], rows: &[ReportRow]) -> Vec<ReportRow> {
+        let sorted = sorted_sources(rows);
+        let moved: Vec<ReportRow> = sidecars
+            .iter()
+            .map(|sidecar| {
+                let timer = Instant::now();
+                let to_path = self.get_sidecar_destination(sidecar);
+                if !sorted.contains(sidecar.owner.as_path()) {
+                    return ReportRow::from_sidecar(
+                        sidecar,
+                        Action::Skipped,
+                        None,
+                        timer.elapsed(),
+                    )
+                    .with_reason(&format!("{:?} was not sorted", sidecar.owner));
+                }
+                if sidecar.full_path == to_path {
+                    return ReportRow::from_sidecar(
+                        sidecar,
+                        Action::Skipped,
+                        Some(to_path),
+                        timer.elapsed(),
+                    )
+                    .with_reason("already in place")
+                    .with_skip(Skip::InPlace);
+                }
+
+                let moved = (|| -> Result<Action> {
+                    if !self.validate_move_paths(&sidecar.full_path, &to_path)? {
+                        return Ok(Action::Conflict);
+                    }
+                    move_file(&sidecar.full_path, &to_path)?;
+                    Ok(Action::Moved)
+                })();
+                match moved {
+                    std::result::Result::Ok(action) => {
+                        ReportRow::from_sidecar(sidecar, action, Some(to_path), timer.elapsed())
+                    }
+                    Err(e) => ReportRow::from_error(&sidecar.full_path, e, timer.elapsed()),
+                }
+            })
+            .collect();
+
         self.verbose(&format!(
-            "Sorting medias in {:?}",
-            self.input.clone().unwrap()
+            "Moved {} sidecar files",
+            count_action(&moved, Action::Moved)
         ));
+        moved
+    }
 
-        self.setup_thread_pool()?;
+    /// Junk files the policy removes: the ones in a folder a media is sorted out of.
+    fn junk_to_delete<'a>(&self, junk: &'a [PathBuf], sorted: &[&Path]) -> Vec<&'a PathBuf> {
+        if self.junk.unwrap_or_default() != JunkPolicy::Delete {
+            return Vec::new();
+        }
+        let dirs: HashSet<&Path> = sorted.iter().filter_map(|path| path.parent()).collect();
+        junk.iter()
+            .filter(|path| path.parent().is_some_and(|dir| dirs.contains(dir)))
+            .collect()
+    }
 
-        let (episodes, mut subtitles) = self.get_medias_from_input(self.skip_subtitles)?;
-        if self.skip_subtitles == false {
-            self.check_subtitles_names(&mut subtitles, &episodes)?;
+    fn delete_junk(&self, junk: &[PathBuf], rows: &[ReportRow]) -> Vec<ReportRow> {
+        let sorted: Vec<&Path> = rows
+            .iter()
+            .filter(|row| matches!(row.action, Action::Moved | Action::Upgraded))
+            .filter(|row| matches!(row.media_type, Some(FileType::Movie | FileType::Series)))
+            .map(|row| row.source.as_path())
+            .collect();
+
+        self.junk_to_delete(junk, &sorted)
+            .into_iter()
+            .map(|path| {
+                let timer = Instant::now();
+                match fs::remove_file(path) {
+                    std::result::Result::Ok(()) => {
+                        ReportRow::from_file(path, FileType::Junk, Action::Deleted, timer.elapsed())
+                    }
+                    Err(e) => ReportRow::from_error(path, e, timer.elapsed()),
+                }
+            })
+            .collect()
+    }
+
+    /// Pair the subtitles with their media. The ones that match none are left in the
+    /// input and reported.
+    fn check_subtitles_names(
+        &self,
+        subtitles: &mut Vec<Subtitle>,
+        episodes: &[Episode],
+    ) -> Vec<ReportRow> {
+        let unmatched = match_subtitles(subtitles, episodes);
+        if unmatched.is_empty() {
+            return Vec::new();
         }
 
-        if episodes.is_empty() {
-            return Ok(());
+        self.info(&format!("\n{} subtitles match no media:", unmatched.len()));
+        unmatched
+            .iter()
+            .map(|subtitle| {
+                self.info(&format!("  - {:?}", subtitle.full_path));
+                ReportRow::from_subtitle(subtitle, Action::Skipped, None, Duration::ZERO)
+                    .with_reason("no matching media")
+            })
+            .collect()
+    }
+
+    /// Pair the subtitles of a sorted library with their media, from the
+    /// `Subtitles/{media name}.{lang}.{ext}` or sidecar layout. Subtitles that do not
+    /// follow either are left where they are rather than guessed.
+    fn pair_sorted_subtitles(&self, subtitles: &mut Vec<Subtitle>, episodes: &[Episode]) {
+        subtitles.retain_mut(|subtitle| {
+            let path = subtitle.full_path.clone();
+            let Some(stem) = path.file_stem().and_then(|stem| stem.to_str()) else {
+                return false;
+            };
+            let media_dir = path.parent().and_then(|dir| {
+                if dir.file_name() == Some("Subtitles".as_ref()) {
+                    dir.parent()
+                } else {
+                    Some(dir)
+                }
+            });
+
+            let episode = episodes.iter().find(|episode| {
+                let media_stem = episode.full_path.file_stem().and_then(|stem| stem.to_str());
+                episode.full_path.parent() == media_dir
+                    && media_stem
+                        .and_then(|media_stem| stem.strip_prefix(media_stem))
+                        .is_some_and(|tags| tags.is_empty() || tags.starts_with('.'))
+            });
+            match episode {
+                Some(episode) => {
+                    subtitle.set_episode(episode.clone());
+                    true
+                }
+                None => {
+                    self.verbose(&format!("Leaving unpaired subtitle {:?}", path));
+                    false
+                }
+            }
+        });
+    }
+
+    fn sort_medias_threaded(&self) -> Result<()> {
+        self.verbose(&format!("Sorting medias in {:?}", self.input));
+
+        self.setup_thread_pool()?;
+
+        let Inputs {
+            mut episodes,
+            mut subtitles,
+            failures,
+            others,
+            archives,
+            parse_times,
+        } = self.get_medias_from_input(self.skip_subtitles)?;
+        if let Err(e) = probe::save_cache() {
+            self.verbose(&format!("Could not save the probe cache: {:?}", e));
+        }
+        let mut rows = failures;
+        let samples = self.take_samples(&mut episodes);
+        rows.extend(self.handle_samples(&samples));
+        self.match_extras(&mut episodes);
+        if self.in_place {
+            self.pair_sorted_subtitles(&mut subtitles, &episodes);
+        } else if !self.skip_subtitles {
+            rows.extend(self.check_subtitles_names(&mut subtitles, &episodes));
+        }
+        // `apply` only moves files, so a plan keeps the subtitles as they are
+        let plan_only = self.dry_run && self.format == OutputFormat::Json;
+        if !plan_only {
+            self.plan_conversions(&mut subtitles);
         }
+        self.number_subtitles(&mut subtitles);
+
+        let duplicates = self.take_duplicates(&mut episodes)?;
+        let (sidecars, junk) = self.find_sidecars(&others, &episodes, &subtitles);
+        let embedded = if self.extract_subtitles && !plan_only {
+            self.embedded_subtitles(&episodes, &subtitles)
+        } else {
+            Vec::new()
+        };
         if self.dry_run {
-            dry_run_sort(
-                &episodes,
-                &subtitles,
-                self.tv_template.clone().unwrap(),
-                self.movie_template.clone().unwrap(),
-            )?;
-            return Ok(());
+            if self.format == OutputFormat::Json {
+                let plan = self.build_plan(&episodes, &subtitles, &sidecars, &duplicates)?;
+                println!("{}", serde_json::to_string_pretty(&plan)?);
+            } else if !episodes.is_empty() {
+                dry_run_sort(
+                    &episodes,
+                    &subtitles,
+                    &sidecars,
+                    self.subtitle_layout(),
+                    |episode| (self.get_dir_name(episode), self.get_dir_label(episode)),
+                )?;
+            }
+            for episode in &episodes {
+                let destination = self.get_destination(episode);
+                let (mut action, mut skip, reason) =
+                    dry_run_status(&episode.full_path, &destination);
+                let mut reason = reason.to_string();
+                if action == Action::Conflict {
+                    if let Some(old) = self.find_upgrade(episode, &destination)? {
+                        action = Action::Skipped;
+                        skip = Skip::DryRun;
+                        reason = format!("dry run, would replace {}", old.describe());
+                    }
+                }
+                rows.push(
+                    ReportRow::from_episode(episode, action, Some(destination), Duration::ZERO)
+                        .with_reason(&reason)
+                        .with_skip(skip),
+                );
+            }
+            rows.extend(subtitles.iter().map(|subtitle| {
+                let destination = self.get_subtitle_destination(subtitle);
+                let (action, skip, reason) = dry_run_status(&subtitle.full_path, &destination);
+                ReportRow::from_subtitle(subtitle, action, Some(destination), Duration::ZERO)
+                    .with_reason(reason)
+                    .with_skip(skip)
+            }));
+            rows.extend(sidecars.iter().map(|sidecar| {
+                let destination = self.get_sidecar_destination(sidecar);
+                let (action, skip, reason) = dry_run_status(&sidecar.full_path, &destination);
+                ReportRow::from_sidecar(sidecar, action, Some(destination), Duration::ZERO)
+                    .with_reason(reason)
+                    .with_skip(skip)
+            }));
+            let sources: Vec<&Path> = episodes
+                .iter()
+                .map(|episode| episode.full_path.as_path())
+                .collect();
+            rows.extend(
+                self.junk_to_delete(&junk, &sources)
+                    .into_iter()
+                    .map(|path| {
+                        ReportRow::from_file(path, FileType::Junk, Action::Skipped, Duration::ZERO)
+                            .with_reason("dry run, would delete")
+                    }),
+            );
+            rows.extend(duplicates.iter().map(|(episode, kept)| {
+                let destination = self
+                    .trash
+                    .as_ref()
+                    .map(|trash| trash_path(trash, &episode.full_path));
+                ReportRow::from_episode(episode, Action::Discarded, destination, Duration::ZERO)
+                    .with_reason(&format!("duplicate of {:?}", kept))
+            }));
+            rows.extend(
+                self.archives_to_delete(&archives, &rows)
+                    .into_iter()
+                    .flat_map(|archive| &archive.volumes)
+                    .map(|path| {
+                        ReportRow::from_file(
+                            path,
+                            FileType::Archive,
+                            Action::Skipped,
+                            Duration::ZERO,
+                        )
+                        .with_reason("dry run, would delete")
+                    })
+                    .collect::<Vec<ReportRow>>(),
+            );
+            rows.extend(embedded.iter().map(|(sidecar, index)| {
+                ReportRow {
+                    source: sidecar.episode.full_path.clone(),
+                    ..ReportRow::from_subtitle(
+                        sidecar,
+                        Action::Skipped,
+                        Some(self.get_subtitle_destination(sidecar)),
+                        Duration::ZERO,
+                    )
+                    .with_reason(&format!("dry run, would extract stream {}", index))
+                }
+            }));
+        } else {
+            rows.extend(self.discard_duplicates(&duplicates));
+            rows.extend(self.move_episodes(&episodes));
+            if !self.skip_subtitles {
+                rows.extend(self.move_subtitles(&subtitles));
+            }
+            let extracted = self.extract_embedded(&embedded, &rows);
+            rows.extend(extracted);
+            let moved = self.move_sidecars(&sidecars, &rows);
+            rows.extend(moved);
+            let deleted = self.delete_junk(&junk, &rows);
+            rows.extend(deleted);
+            let deleted = self.delete_archives(&archives, &rows);
+            rows.extend(deleted);
+            if self.in_place {
+                for input in &self.input {
+                    remove_empty_dirs(input)?;
+                }
+            }
+            if let Some(undo) = &self.undo {
+                Plan::undo(&rows)?.save(undo)?;
+                self.info(&format!("Undo plan written to {:?}", undo));
+            }
         }
-        self.move_episodes(&episodes)?;
-        if !self.skip_subtitles {
-            self.move_subtitles(&subtitles)?;
+        for row in rows.iter_mut() {
+            if let Some(parse_time) = parse_times.get(&row.source) {
+                row.duration_ms += parse_time.as_millis();
+            }
+        }
+        self.print_probe_summary(&episodes);
+        if !archives.is_empty() {
+            if let Err(e) = fs::remove_dir_all(extraction_dir()) {
+                self.verbose(&format!("Could not remove the extracted archives: {:?}", e));
+            }
+        }
+
+        if let Some(report) = &self.report {
+            write_report(report, &rows)?;
+            self.info(&format!("Report written to {:?}", report));
+        }
+
+        let unsorted = count_action(&rows, Action::Error);
+        if unsorted > 0 {
+            self.print_unsorted_summary(&rows);
+            bail!("{} of {} files could not be sorted", unsorted, rows.len());
         }
 
         Ok(())
     }
 
+    /// Remove the samples from the medias.
+    fn take_samples(&self, episodes: &mut Vec<Episode>) -> Vec<Episode> {
+        let max_size = self.sample_size.unwrap_or_default();
+        let max_duration = self.sample_duration.unwrap_or_default();
+        let (samples, kept): (Vec<Episode>, Vec<Episode>) = episodes
+            .drain(..)
+            .partition(|episode| extra::is_sample(episode, max_size, max_duration));
+        *episodes = kept;
+
+        for sample in &samples {
+            self.verbose(&format!("Found sample {:?}", sample.full_path));
+        }
+        samples
+    }
+
+    /// Leave the samples in the input, or delete them when the sample policy says so.
+    /// Samples in a sorted library are never deleted.
+    fn handle_samples(&self, samples: &[Episode]) -> Vec<ReportRow> {
+        let delete = self.samples.unwrap_or_default() == SamplePolicy::Delete && !self.in_place;
+        samples
+            .iter()
+            .map(|sample| {
+                let timer = Instant::now();
+                let row = |action| ReportRow::from_episode(sample, action, None, timer.elapsed());
+                if !delete {
+                    return row(Action::Skipped).with_reason("sample");
+                } else if self.dry_run {
+                    return row(Action::Skipped).with_reason("dry run, would delete sample");
+                }
+                match fs::remove_file(&sample.full_path) {
+                    std::result::Result::Ok(()) => row(Action::Deleted).with_reason("sample"),
+                    Err(e) => ReportRow::from_error(&sample.full_path, e, timer.elapsed()),
+                }
+            })
+            .collect()
+    }
+
+    /// Give the extras the name, type and year of the title they belong to when it is
+    /// sorted in the same run, so they end up in its folder. Those movies get a folder of
+    /// their own.
+    fn match_extras(&self, episodes: &mut [Episode]) {
+        let titles: Vec<(String, bool, Option<u32>, u32)> = episodes
+            .iter()
+            .filter(|episode| episode.extra.is_none())
+            .map(|episode| {
+                let name = episode.name.clone();
+                (name, episode.is_movie, episode.year, episode.season)
+            })
+            .collect();
+
+        for episode in episodes
+            .iter_mut()
+            .filter(|episode| episode.extra.is_some())
+        {
+            let title = titles
+                .iter()
+                .find(|(name, ..)| name.eq_ignore_ascii_case(&episode.name));
+            if let Some((name, is_movie, year, season)) = title {
+                episode.name = name.clone();
+                episode.is_movie = *is_movie;
+                episode.year = *year;
+                episode.season = *season;
+            }
+        }
+
+        // Plex and Jellyfin only find the extras of a movie that is in a folder of its own
+        let with_extras: HashSet<(String, Option<u32>)> = episodes
+            .iter()
+            .filter(|episode| episode.extra.is_some() && episode.is_movie)
+            .map(|episode| (episode.name.to_lowercase(), episode.year))
+            .collect();
+        for episode in episodes.iter_mut().filter(|episode| {
+            episode.extra.is_none()
+                && episode.is_movie
+                && with_extras.contains(&(episode.name.to_lowercase(), episode.year))
+        }) {
+            episode.own_folder = true;
+        }
+    }
+
+    /// Remove the duplicates that should not be sorted from the episodes, returning each
+    /// of them with the path of the copy that was kept.
+    fn take_duplicates(&self, episodes: &mut Vec<Episode>) -> Result<Vec<(Episode, PathBuf)>> {
+        let keep = self.keep.unwrap_or_default();
+        let groups = find_duplicates(episodes, |episode| self.get_destination(episode), keep)?;
+        self.print_duplicates(&groups);
+
+        let discarded: HashMap<&Path, &Path> = groups
+            .iter()
+            .flat_map(|group| {
+                group
+                    .discarded(keep)
+                    .into_iter()
+                    .map(|copy| (copy.path.as_path(), group.kept().path.as_path()))
+            })
+            .collect();
+
+        let mut duplicates = Vec::new();
+        let mut kept = Vec::new();
+        for episode in episodes.drain(..) {
+            match discarded.get(episode.full_path.as_path()) {
+                Some(kept_path) => duplicates.push((episode, kept_path.to_path_buf())),
+                None => kept.push(episode),
+            }
+        }
+        *episodes = kept;
+
+        Ok(duplicates)
+    }
+
+    fn print_duplicates(&self, groups: &[DuplicateGroup]) {
+        if groups.is_empty() {
+            return;
+        }
+        let keep = self.keep.unwrap_or_default();
+
+        self.info(&format!(
+            "\n{} medias were found several times:",
+            groups.len()
+        ));
+        for group in groups {
+            self.info(&format!("  {}", group.title));
+            for (i, copy) in group.copies.iter().enumerate() {
+                let status = if copy.in_library {
+                    "library"
+                } else if i == 0 || keep == KeepPolicy::All {
+                    "keep"
+                } else {
+                    "discard"
+                };
+                self.info(&format!(
+                    "    {:<8} {:?} ({})",
+                    status,
+                    copy.path,
+                    copy.describe()
+                ));
+            }
+        }
+    }
+
+    /// Move the discarded duplicates to the trash folder, or leave them in the input.
+    fn discard_duplicates(&self, duplicates: &[(Episode, PathBuf)]) -> Vec<ReportRow> {
+        duplicates
+            .iter()
+            .map(|(episode, kept)| {
+                let timer = Instant::now();
+                let reason = format!("duplicate of {:?}", kept);
+                let trashed = match &self.trash {
+                    Some(trash) => (|| -> Result<Option<PathBuf>> {
+                        fs::create_dir_all(trash)?;
+                        let to_path = trash_path(trash, &episode.full_path);
+                        move_file(&episode.full_path, &to_path)?;
+                        Ok(Some(to_path))
+                    })(),
+                    None => Ok(None),
+                };
+                match trashed {
+                    std::result::Result::Ok(destination) => ReportRow::from_episode(
+                        episode,
+                        Action::Discarded,
+                        destination,
+                        timer.elapsed(),
+                    )
+                    .with_reason(&reason),
+                    Err(e) => ReportRow::from_error(&episode.full_path, e, timer.elapsed()),
+                }
+            })
+            .collect()
+    }
+
+    /// Every move a real run would do, in a stable order.
+    fn build_plan(
+        &self,
+        episodes: &[Episode],
+        subtitles: &[Subtitle],
+        sidecars: &[SidecarFile],
+        duplicates: &[(Episode, PathBuf)],
+    ) -> Result<Plan> {
+        let mut moves = Vec::new();
+        for episode in episodes {
+            let media_type = FileType::of(episode).as_str();
+            let destination = self.get_destination(episode);
+            if destination != episode.full_path {
+                moves.push(PlannedMove::new(
+                    &episode.full_path,
+                    destination,
+                    media_type,
+                )?);
+            }
+        }
+        for subtitle in subtitles {
+            let destination = self.get_subtitle_destination(subtitle);
+            if destination != subtitle.full_path {
+                moves.push(PlannedMove::new(
+                    &subtitle.full_path,
+                    destination,
+                    FileType::Subtitle.as_str(),
+                )?);
+            }
+        }
+        for sidecar in sidecars {
+            let destination = self.get_sidecar_destination(sidecar);
+            if destination != sidecar.full_path {
+                moves.push(PlannedMove::new(
+                    &sidecar.full_path,
+                    destination,
+                    FileType::Sidecar.as_str(),
+                )?);
+            }
+        }
+        if let Some(trash) = &self.trash {
+            for (episode, _) in duplicates {
+                moves.push(PlannedMove::new(
+                    &episode.full_path,
+                    trash_path(trash, &episode.full_path),
+                    "duplicate",
+                )?);
+            }
+        }
+
+        Ok(Plan::new(moves))
+    }
+
+    /// List the files left in their input directory, with the reason.
+    fn print_unsorted_summary(&self, rows: &[ReportRow]) {
+        self.info("\nUnsorted files:");
+        for row in rows.iter().filter(|row| row.action == Action::Error) {
+            self.info(&format!(
+                "  - {:?}: {}",
+                row.source,
+                row.reason.as_deref().unwrap_or_default()
+            ));
+        }
+    }
+
+    /// List the medias that could not be probed and were classified from their names only.
+    fn print_probe_summary(&self, episodes: &[Episode]) {
+        let unprobed: Vec<&Episode> = episodes
+            .iter()
+            .filter(|episode| episode.probe_error.is_some())
+            .collect();
+
+        if unprobed.is_empty() {
+            return;
+        }
+        if !probe::is_available() {
+            self.info(&format!(
+                "\n{} medias were classified from their file names only, install ffprobe to probe them",
+                unprobed.len()
+            ));
+            return;
+        }
+
+        self.info(&format!(
+            "\n{} medias could not be probed and were classified from their file names:",
+            unprobed.len()
+        ));
+        for episode in unprobed {
+            self.info(&format!(
+                "  - {:?}: {}",
+                episode.full_path,
+                episode.probe_error.as_deref().unwrap_or_default()
+            ));
+        }
+    }
+
     fn find_or_create_dir(
         &self,
         episode: &Episode,
@@ -389,107 +1548,162 @@ impl Sort {
             bail!("Episode name is unknow");
         }
 
-        let dest_dir: PathBuf = self.get_dir_name(&episode);
+        let dest_dir: PathBuf = self.get_dir_name(episode);
 
         {
             let mut dir_set_guard = dir_set.lock().unwrap();
 
             if !dir_set_guard.contains(&dest_dir) && !dest_dir.exists() {
-                fs::create_dir_all(&dest_dir).expect("Failed to create directory");
+                fs::create_dir_all(&dest_dir)?;
                 dir_set_guard.insert(dest_dir.clone());
             }
         }
 
-        if !episode.is_movie {
-            let serie_dir = PathBuf::from(dest_dir.clone()).join(&episode.name);
-            if dir_set.lock().unwrap().contains(&serie_dir) {
-                return Ok(serie_dir);
-            }
+        let media_dir = dest_dir.join(media_subdir(episode));
+        if media_dir != dest_dir && !dir_set.lock().unwrap().contains(&media_dir) {
+            fs::create_dir_all(&media_dir)?;
+            dir_set.lock().unwrap().insert(media_dir.clone());
+        }
 
-            let season_dir: PathBuf = PathBuf::from(serie_dir.clone()).join(format!(
-                "S{:02}",
-                if episode.season == 0 {
-                    1
-                } else {
-                    episode.season
-                }
-            ));
+        Ok(media_dir)
+    }
 
-            if dir_set.lock().unwrap().contains(&season_dir) {
-                return Ok(season_dir);
-            }
+    /// Directory of a media inside its library, `{library}/{name}/Sxx` for series.
+    fn get_media_dir(&self, episode: &Episode) -> PathBuf {
+        self.get_dir_name(episode).join(media_subdir(episode))
+    }
 
-            fs::create_dir_all(&season_dir).expect("Failed to create directory");
-            dir_set.lock().unwrap().insert(season_dir.clone());
+    fn get_destination(&self, episode: &Episode) -> PathBuf {
+        self.get_media_dir(episode).join(media_filename(episode))
+    }
 
-            return Ok(season_dir);
-        }
+    fn get_subtitle_destination(&self, subtitle: &Subtitle) -> PathBuf {
+        self.get_media_dir(&subtitle.episode)
+            .join(subtitle_path(subtitle, self.subtitle_layout()))
+    }
 
-        Ok(dest_dir)
+    fn subtitle_layout(&self) -> SubtitleLayout {
+        self.subtitle_layout.unwrap_or_default()
     }
 
+    /// Number the subtitles that would get the same name, e.g. two English subtitles of a
+    /// media, so that none of them overwrites another one or a file already there.
+    fn number_subtitles(&self, subtitles: &mut [Subtitle]) {
+        let mut order: Vec<usize> = (0..subtitles.len()).collect();
+        order.sort_by(|&a, &b| subtitles[a].full_path.cmp(&subtitles[b].full_path));
+
+        let mut taken = HashSet::new();
+        for i in order {
+            let subtitle = &mut subtitles[i];
+            let mut destination = self.get_subtitle_destination(subtitle);
+            let mut number = 1;
+            while taken.contains(&destination)
+                || (destination.exists() && destination != subtitle.full_path)
+            {
+                number += 1;
+                subtitle.number = Some(number);
+                destination = self.get_subtitle_destination(subtitle);
+            }
+            taken.insert(destination);
+        }
+    }
+
+    /// Library directory of a media. The first matching rule picks the output root and
+    /// template, otherwise the movies or series output is used, then the template folder
+    /// inside the output directory. Templates can use release placeholders like `{resolution}`.
     fn get_dir_name(&self, episode: &Episode) -> PathBuf {
-        let mut dest_dir: PathBuf =
-            PathBuf::from(&<Option<PathBuf> as Clone>::clone(&self.output).unwrap());
-        if episode.is_movie {
-            dest_dir.push(self.movie_template.clone().unwrap());
+        let type_output = if episode.is_movie {
+            &self.movies_output
         } else {
-            dest_dir.push(self.tv_template.clone().unwrap());
+            &self.series_output
+        };
+
+        if let Some(route) = self.routes.iter().find(|route| route.matches(episode)) {
+            let output = route
+                .output
+                .clone()
+                .or_else(|| type_output.clone())
+                .or_else(|| self.output.clone())
+                .unwrap_or_default();
+            return match &route.template {
+                Some(template) => output.join(episode.release.format_template(template)),
+                None => output,
+            };
+        }
+
+        if let Some(type_output) = type_output {
+            return type_output.clone();
         }
 
+        let mut dest_dir: PathBuf = self.output.clone().unwrap_or_default();
+        let template = if episode.is_movie {
+            self.movie_template.as_ref().unwrap()
+        } else {
+            self.tv_template.as_ref().unwrap()
+        };
+        dest_dir.push(episode.release.format_template(template));
+
         dest_dir
     }
 
-    fn validate_move_paths(&self, from: &PathBuf, to: &PathBuf) -> Result<()> {
+    /// Library directory as shown in dry runs, with the rule that picked it.
+    fn get_dir_label(&self, episode: &Episode) -> String {
+        let dest_dir = self.get_dir_name(episode).display().to_string();
+        match self.routes.iter().find(|route| route.matches(episode)) {
+            Some(route) => format!("{} (rule {})", dest_dir, route.label()),
+            None => dest_dir,
+        }
+    }
+
+    /// Check a move can be done, returning `false` when the destination already exists.
+    fn validate_move_paths(&self, from: &PathBuf, to: &PathBuf) -> Result<bool> {
         if !from.exists() {
             bail!("Source path does not exist: {:?}", from);
         } else if !from.is_file() {
             bail!("Source path is not a file: {:?}", from);
-        } else if from.parent() == to.parent() {
+        } else if from.parent() == to.parent() && !self.in_place {
             bail!("Source and destination directories are the same");
         } else if to.exists() {
             self.verbose(&format!("Destination path already exists: {:?}", to));
-            return Ok(());
+            return Ok(false);
         }
 
-        Ok(())
+        Ok(true)
     }
 
-    fn execute_file_move(&self, from: &PathBuf, to: &PathBuf) -> Result<()> {
-        if is_on_same_drive(&from, &to) {
-            move_by_rename(&from, &to)?;
-        } else {
-            move_by_copy(&from, &to)?;
-        }
-        Ok(())
+    fn execute_file_move(&self, from: &Path, to: &Path) -> Result<()> {
+        move_file(from, to)
     }
 
-    fn create_webhook_payload(&self, episode: &Episode) -> String {
-        if episode.is_movie {
-            format!("Added: `{}` to the library", episode.name)
+    fn create_webhook_payload(&self, episode: &Episode, action: Action) -> String {
+        let title = if episode.is_movie {
+            episode.name.clone()
+        } else if episode.episode >= 100 {
+            format!(
+                "{} - S{:02}E{:03}",
+                episode.name, episode.season, episode.episode
+            )
         } else {
-            if episode.episode >= 100 {
-                format!(
-                    "Added: `{} - S{:02}E{:03}` to the library",
-                    episode.name, episode.season, episode.episode
-                )
-            } else {
-                format!(
-                    "Added: `{} - S{:02}E{:02}` to the library",
-                    episode.name, episode.season, episode.episode
-                )
-            }
+            format!(
+                "{} - S{:02}E{:02}",
+                episode.name, episode.season, episode.episode
+            )
+        };
+
+        match action {
+            Action::Upgraded => format!("Upgraded: `{}` in the library", title),
+            _ => format!("Added: `{}` to the library", title),
         }
     }
 
-    fn send_webhook(&self, episode: &Episode, pb: &ProgressBar) -> Result<()> {
+    fn send_webhook(&self, episode: &Episode, action: Action, pb: &ProgressBar) -> Result<()> {
         if let Some(webhook) = self.webhook.as_ref() {
             if !webhook.is_empty() && webhook != "default" {
                 let pb_msg = pb.message().to_string();
                 if self.verbose {
                     pb.set_message(format!("{} - Sending webhook", pb_msg));
                 }
-                let message = self.create_webhook_payload(episode);
+                let message = self.create_webhook_payload(episode, action);
 
                 let payload = json!({
                     "content": message,
@@ -514,32 +1728,37 @@ impl Sort {
         Ok(())
     }
 
-    fn get_new_filename(&self, episode: &Episode) -> String {
-        if episode.is_movie {
-            format!("{}.{}", episode.name, episode.extension)
-        } else {
-            if episode.episode >= 100 {
-                format!(
-                    "{} - E{:03}.{}",
-                    episode.name, episode.episode, episode.extension
-                )
-            } else {
-                format!(
-                    "{} - E{:02}.{}",
-                    episode.name, episode.episode, episode.extension
-                )
-            }
-        }
-    }
-
-    fn move_media(&self, episode: &Episode, dest_dir: &PathBuf, pb: &ProgressBar) -> Result<()> {
+    fn move_media(
+        &self,
+        episode: &Episode,
+        dest_dir: &Path,
+        pb: &ProgressBar,
+    ) -> Result<(Action, PathBuf, Option<String>)> {
         let timer = Instant::now();
         // let from_path = self.input.clone().unwrap().join(&episode.filename);
         let from_path = episode.full_path.clone();
-        let to_path = dest_dir.join(self.get_new_filename(episode));
+        let to_path = dest_dir.join(media_filename(episode));
 
-        self.validate_move_paths(&from_path, &to_path)?;
-        self.execute_file_move(&from_path, &to_path)?;
+        if from_path == to_path {
+            return Ok((
+                Action::Skipped,
+                to_path,
+                Some("already in place".to_string()),
+            ));
+        }
+        let mut action = Action::Moved;
+        let mut reason = None;
+        if !self.validate_move_paths(&from_path, &to_path)? {
+            match self.find_upgrade(episode, &to_path)? {
+                Some(old) => {
+                    reason = Some(self.replace_media(&from_path, &to_path, &old)?);
+                    action = Action::Upgraded;
+                }
+                None => return Ok((Action::Conflict, to_path, None)),
+            }
+        } else {
+            self.execute_file_move(&from_path, &to_path)?;
+        }
         if self.verbose {
             pb.set_message(format!(
                 "Moved {} to {} in {:?}",
@@ -548,9 +1767,81 @@ impl Sort {
                 timer.elapsed()
             ));
         }
-        self.send_webhook(&episode, pb)?;
-        Ok(())
+        if let Err(e) = self.send_webhook(episode, action, pb) {
+            pb.println(format!("Warning: {:#}", e));
+        }
+        Ok((action, to_path, reason))
     }
+
+    /// The library copy at `to_path` when upgrades are enabled and the episode is better.
+    fn find_upgrade(&self, episode: &Episode, to_path: &Path) -> Result<Option<MediaCopy>> {
+        if !self.upgrade || !to_path.is_file() {
+            return Ok(None);
+        }
+        let new = MediaCopy::from_episode(episode)?;
+        let old = MediaCopy::from_library(to_path)?;
+
+        Ok(self.quality.is_upgrade(&new, &old).then_some(old))
+    }
+
+    /// Put the new copy in place of the old one, which is moved to the trash directory or
+    /// deleted. The new copy is first moved next to the old one, so the old copy is only
+    /// removed once the new one is safely in the library.
+    fn replace_media(&self, from: &Path, to: &Path, old: &MediaCopy) -> Result<String> {
+        let file_name = to.file_name().unwrap_or_default().to_string_lossy();
+        let staging = to.with_file_name(format!(".{}.upgrade", file_name));
+        move_file(from, &staging)?;
+
+        let archived = (|| -> Result<String> {
+            match &self.trash {
+                Some(trash) => {
+                    fs::create_dir_all(trash)?;
+                    let trash_to = trash_path(trash, to);
+                    move_file(to, &trash_to)?;
+                    Ok(format!("old copy moved to {:?}", trash_to))
+                }
+                None => {
+                    fs::remove_file(to)?;
+                    Ok("old copy deleted".to_string())
+                }
+            }
+        })();
+        let archived = match archived {
+            std::result::Result::Ok(archived) => archived,
+            Err(e) => {
+                // Keep both errors when the new copy cannot go back, and say where it is
+                if let Err(rollback) = move_file(&staging, from) {
+                    bail!(
+                        "Could not remove the old copy {:?}: {:#}. Could not move the new copy back to {:?} either: {:#}. It was left at {:?}",
+                        to,
+                        e,
+                        from,
+                        rollback,
+                        staging
+                    );
+                }
+                bail!("Could not remove the old copy {:?}: {:#}", to, e);
+            }
+        };
+        fs::rename(&staging, to).with_context(|| {
+            format!(
+                "Could not move the new copy to {:?}, it was left at {:?}",
+                to, staging
+            )
+        })?;
+
+        Ok(format!("replaced {}, {}", old.describe(), archived))
+    }
+}
+
+/// Move a file, renaming it when possible and copying it across drives.
+pub(crate) fn move_file(from: &Path, to: &Path) -> Result<()> {
+    if is_on_same_drive(from, to) {
+        move_by_rename(from, to)?;
+    } else {
+        move_by_copy(from, to)?;
+    }
+    Ok(())
 }
 
 #[cfg(target_os = "windows")]
@@ -567,11 +1858,15 @@ fn is_on_same_drive<P: AsRef<Path>>(path1: P, path2: P) -> bool {
 #[cfg(target_os = "linux")]
 fn is_on_same_drive<P: AsRef<Path>, Q: AsRef<Path>>(path1: P, path2: Q) -> bool {
     use std::fs;
+    use std::os::unix::fs::MetadataExt;
 
-    let fs1 = fs::metadata(path1).expect("Unable to read metadata").dev();
-    let fs2 = fs::metadata(path2).expect("Unable to read metadata").dev();
-
-    fs1 == fs2
+    // The destination does not exist yet, so compare with its directory
+    let path2 = path2.as_ref();
+    let dir2 = path2.parent().unwrap_or(path2);
+    match (fs::metadata(path1), fs::metadata(dir2)) {
+        (std::result::Result::Ok(fs1), std::result::Result::Ok(fs2)) => fs1.dev() == fs2.dev(),
+        _ => false,
+    }
 }
 
 fn move_by_rename<P: AsRef<Path>>(from: P, to: P) -> Result<()> {
@@ -597,248 +1892,622 @@ fn get_progress_bar(len: usize) -> ProgressBar {
     pb
 }
 
-fn sanitize_filename(filename: &str) -> String {
-    let invalid_chars = ['<', '>', '"', '/', '|', '?', '*', ':'];
-    let reserved_names = [
-        "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
-        "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
-    ];
-    println!("Sanitizing filename: {}", filename);
-    // Skip drive letter (e.g., "C:") when sanitizing
-    let (drive, rest) = if filename.len() > 2 && &filename[1..2] == ":" {
-        filename.split_at(2)
-    } else {
-        ("", filename)
-    };
+/// Media and subtitles found in the inputs, with the files that could not be parsed.
+struct Inputs {
+    episodes: Vec<Episode>,
+    subtitles: Vec<Subtitle>,
+    failures: Vec<ReportRow>,
+    /// Files that are neither medias nor subtitles, e.g. `.nfo` files or junk.
+    others: Vec<PathBuf>,
+    /// Extracted archives, with the folder they were extracted to.
+    archives: Vec<(Archive, PathBuf)>,
+    parse_times: HashMap<PathBuf, Duration>,
+}
+
+/// Temporary folder the archives of this run are extracted to.
+fn extraction_dir() -> PathBuf {
+    std::env::temp_dir().join(format!("MediaSort-{}", std::process::id()))
+}
 
-    // Sanitize the rest of the path, ignoring invalid characters
-    let sanitized: String = rest
-        .chars()
-        .filter(|c| !invalid_chars.contains(c))
-        .collect();
-    let sanitized = sanitized.trim().to_string();
+/// Sources of the files that are now in the library, moved or already there.
+fn sorted_sources(rows: &[ReportRow]) -> HashSet<&Path> {
+    rows.iter()
+        .filter(|row| {
+            matches!(row.action, Action::Moved | Action::Upgraded) || row.skip == Skip::InPlace
+        })
+        .map(|row| row.source.as_path())
+        .collect()
+}
 
-    // Ensure the sanitized filename is not a reserved name
-    let sanitized = if reserved_names.contains(&sanitized.as_str()) {
-        format!("{}_", sanitized)
+fn count_action(rows: &[ReportRow], action: Action) -> usize {
+    rows.iter().filter(|row| row.action == action).count()
+}
+
+/// Folder of a media inside its library: `{name}/Sxx` for series, none for movies or
+/// `Movie (2010)` for the ones with extras, and `Trailers` or another extras folder in the
+/// folder of their title for extras.
+fn media_subdir(episode: &Episode) -> PathBuf {
+    let title_dir = PathBuf::from(if episode.is_movie {
+        movie_title(episode)
     } else {
-        sanitized
-    };
+        episode.name.clone()
+    });
+    if let Some(folder) = episode.extra.and_then(ExtraKind::folder) {
+        return title_dir.join(folder);
+    }
+    match (episode.is_movie, episode.own_folder) {
+        (true, true) => title_dir,
+        (true, false) => PathBuf::new(),
+        (false, _) => title_dir.join(season_dir_name(episode)),
+    }
+}
 
-    // Reattach the drive letter
-    format!("{}{}", drive, sanitized)
+/// Name of a movie with its year, `Movie (2010)`, so that two movies of the same name
+/// never share a file.
+fn movie_title(episode: &Episode) -> String {
+    match episode.year {
+        Some(year) => format!("{} ({})", episode.name, year),
+        None => episode.name.clone(),
+    }
 }
 
-// Modified print_tree and print_tree_inner functions to handle subtitles
-fn print_tree(
-    dry_map: &HashMap<String, HashMap<String, HashMap<String, Vec<(String, bool)>>>>,
-    prefix: &str,
-    is_last: bool,
-) {
-    let connector = if is_last { "└─" } else { "├─" };
+/// `Sxx`, or `Specials` for season 0 when the episode is a special. Other episodes
+/// without a season go to `S01`.
+fn season_dir_name(episode: &Episode) -> String {
+    match episode.season {
+        0 if episode.special => "Specials".to_string(),
+        0 => "S01".to_string(),
+        season => format!("S{:02}", season),
+    }
+}
 
-    for (i, (media_key, series_map)) in dry_map.iter().enumerate() {
-        let is_last_media = i == dry_map.len() - 1;
-        println!("{}{} {}/", prefix, connector, media_key);
-        let new_prefix = format!("{}{}", prefix, if is_last_media { "   " } else { "│  " });
+/// File name of a sorted media, e.g. `Movie (2010).mkv`, `Show - E01.mkv`, or
+/// `Show - E101.mkv` past 99 episodes. Specials named from a database get their title,
+/// `Show - E01 - Title.mkv`. Extras and specials without a number keep their own name.
+fn media_filename(episode: &Episode) -> String {
+    if episode.extra.is_some() || (episode.special && episode.episode == 0) {
+        return episode.filename.clone();
+    } else if episode.is_movie {
+        return format!("{}.{}", movie_title(episode), episode.extension);
+    }
 
-        for (j, (series_key, season_map)) in series_map.iter().enumerate() {
-            let is_last_series = j == series_map.len() - 1;
-            print_tree_inner(&season_map, series_key, &new_prefix, is_last_series);
+    let number = if episode.episode >= 100 {
+        format!("E{:03}", episode.episode)
+    } else {
+        format!("E{:02}", episode.episode)
+    };
+    match &episode.title {
+        Some(title) => format!(
+            "{} - {} - {}.{}",
+            episode.name,
+            number,
+            sanitize_filename(title),
+            episode.extension
+        ),
+        None => format!("{} - {}.{}", episode.name, number, episode.extension),
+    }
+}
+
+/// Path of a sorted subtitle inside the folder of its media, either
+/// `Subtitles/{media name}[.n].{lang}[.forced][.sdh][.cc].{ext}`, or the sidecar
+/// `{media name}[.n][.{lang}][.forced][.sdh].{ext}` that media servers read, where
+/// closed captions count as SDH and an unknown language is left out.
+fn subtitle_path(subtitle: &Subtitle, layout: SubtitleLayout) -> PathBuf {
+    let media = media_filename(&subtitle.episode);
+    let sidecar = layout == SubtitleLayout::Sidecar;
+
+    let mut tags: Vec<String> = subtitle.number.iter().map(u32::to_string).collect();
+    match (&subtitle.language, sidecar) {
+        (Some(language), _) => tags.push(language.clone()),
+        (None, false) => tags.push("Unknown".to_string()),
+        (None, true) => {}
+    }
+    for (flag, tag) in [
+        (subtitle.forced, "forced"),
+        (subtitle.sdh || (sidecar && subtitle.cc), "sdh"),
+        (subtitle.cc && !sidecar, "cc"),
+    ] {
+        if flag {
+            tags.push(tag.to_string());
         }
     }
+    tags.push(match subtitle.format {
+        Some(format) => format.extension().to_string(),
+        None => subtitle
+            .full_path
+            .extension()
+            .unwrap()
+            .to_string_lossy()
+            .to_string(),
+    });
+
+    let file_name = Path::new(&media).with_extension(tags.join("."));
+    if sidecar {
+        file_name
+    } else {
+        Path::new("Subtitles").join(file_name)
+    }
 }
 
-fn print_tree_inner(
-    season_map: &HashMap<String, Vec<(String, bool)>>,
-    series_key: &str,
-    prefix: &str,
-    is_last: bool,
-) {
-    let connector = if is_last { "└─" } else { "├─" };
+/// What a real run would do with a file, and why it is not moved now.
+fn dry_run_status(source: &Path, destination: &Path) -> (Action, Skip, &'static str) {
+    if source == destination {
+        (Action::Skipped, Skip::InPlace, "already in place")
+    } else if destination.exists() {
+        (Action::Conflict, Skip::No, "destination already exists")
+    } else {
+        (Action::Skipped, Skip::DryRun, "dry run")
+    }
+}
 
-    if !series_key.is_empty() {
-        println!("{}{} {}/", prefix, connector, series_key);
+/// Remove the folders left empty under `root` by a run, keeping `root` itself.
+fn remove_empty_dirs(root: &Path) -> Result<()> {
+    for entry in fs::read_dir(root)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            remove_empty_dirs(&path)?;
+            if fs::read_dir(&path)?.next().is_none() {
+                fs::remove_dir(&path)?;
+            }
+        }
     }
-    let new_prefix = format!("{}{}", prefix, if is_last { "   " } else { "│  " });
+    Ok(())
+}
 
-    for (k, (season_key, items)) in season_map.iter().enumerate() {
-        let is_last_season = k == season_map.len() - 1;
+/// A folder or file of the dry run tree.
+#[derive(Default)]
+struct TreeNode {
+    children: BTreeMap<NaturalKey, TreeNode>,
+    conflict: bool,
+}
 
-        if !season_key.is_empty() {
-            println!(
-                "{}{} {}/",
-                new_prefix,
-                if is_last_season { "└─" } else { "├─" },
-                season_key
-            );
+impl TreeNode {
+    fn insert(&mut self, path: &Path, conflict: bool) {
+        let mut node = self;
+        for component in path.iter() {
+            node = node
+                .children
+                .entry(NaturalKey::new(&component.to_string_lossy()))
+                .or_default();
         }
-        let item_prefix = if is_last_season {
-            format!("{}   ", new_prefix)
-        } else {
-            format!("{}│  ", new_prefix)
-        };
+        node.conflict = conflict;
+    }
 
-        // Separate episodes and subtitles
-        let (episodes, subtitles): (Vec<_>, Vec<_>) =
-            items.iter().partition(|(_, is_subtitle)| !is_subtitle);
-
-        // Print episodes
-        for (l, (item, _)) in episodes.iter().enumerate() {
-            let is_last_item = l == episodes.len() - 1 && subtitles.is_empty();
-            println!(
-                "{}{} {}",
-                item_prefix,
-                if is_last_item { "└─" } else { "├─" },
-                item
-            );
+    /// Folders first, then files, each in natural order so `E100` comes after `E99`.
+    fn render(&self, prefix: &str, lines: &mut Vec<String>) {
+        let (folders, files): (Vec<_>, Vec<_>) = self
+            .children
+            .iter()
+            .partition(|(_, child)| !child.children.is_empty());
+        let count = folders.len() + files.len();
+
+        for (i, (name, child)) in folders.into_iter().chain(files).enumerate() {
+            let is_last = i == count - 1;
+            let connector = if is_last { "└─" } else { "├─" };
+            if child.children.is_empty() {
+                let conflict = if child.conflict {
+                    "  [conflict: already exists]"
+                } else {
+                    ""
+                };
+                lines.push(format!("{}{} {}{}", prefix, connector, name.0, conflict));
+            } else {
+                lines.push(format!("{}{} {}/", prefix, connector, name.0));
+                let prefix = format!("{}{}", prefix, if is_last { "   " } else { "│  " });
+                child.render(&prefix, lines);
+            }
         }
+    }
+}
 
-        // Print subtitles in a "Subtitles" folder
-        if !subtitles.is_empty() {
-            let subtitles_connector = if is_last_season { "└─" } else { "├─" };
-            println!("{}{} Subtitles/", item_prefix, subtitles_connector);
-            let subtitle_prefix = format!("{}   ", item_prefix);
+/// Sort key comparing the numbers inside names by value.
+#[derive(PartialEq, Eq)]
+struct NaturalKey(String);
 
-            for (m, (subtitle, _)) in subtitles.iter().enumerate() {
-                let is_last_subtitle = m == subtitles.len() - 1;
-                println!(
-                    "{}{} {}",
-                    subtitle_prefix,
-                    if is_last_subtitle { "└─" } else { "├─" },
-                    subtitle
-                );
+impl NaturalKey {
+    fn new(name: &str) -> Self {
+        Self(name.to_string())
+    }
+
+    fn parts(&self) -> Vec<(String, u64)> {
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut digits = String::new();
+        for c in self.0.chars() {
+            if c.is_ascii_digit() {
+                digits.push(c);
+            } else {
+                if !digits.is_empty() {
+                    parts.push((text.to_lowercase(), digits.parse().unwrap_or(0)));
+                    text.clear();
+                    digits.clear();
+                }
+                text.push(c);
             }
         }
+        parts.push((text.to_lowercase(), digits.parse().unwrap_or(0)));
+        parts
+    }
+}
+
+impl Ord for NaturalKey {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.parts()
+            .cmp(&other.parts())
+            .then_with(|| self.0.cmp(&other.0))
     }
 }
 
-// Modified dry_run_sort function
+impl PartialOrd for NaturalKey {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Render the tree of a dry run, with the same names as a real run, followed by the
+/// number of files per library, show and action. `library` gives the library directory
+/// of a media and how to show it.
+pub fn dry_run_tree(
+    episodes: &[Episode],
+    subtitles: &[Subtitle],
+    sidecars: &[SidecarFile],
+    layout: SubtitleLayout,
+    library: impl Fn(&Episode) -> (PathBuf, String),
+) -> Vec<String> {
+    let mut libraries: BTreeMap<NaturalKey, TreeNode> = BTreeMap::new();
+    let mut library_counts: BTreeMap<NaturalKey, usize> = BTreeMap::new();
+    let mut show_counts: BTreeMap<NaturalKey, usize> = BTreeMap::new();
+    let mut conflicts = 0;
+    let mut in_place = 0;
+
+    let files = episodes
+        .iter()
+        .map(|episode| {
+            let path = media_subdir(episode).join(media_filename(episode));
+            (episode, &episode.full_path, path)
+        })
+        .chain(subtitles.iter().map(|subtitle| {
+            let path = media_subdir(&subtitle.episode).join(subtitle_path(subtitle, layout));
+            (&subtitle.episode, &subtitle.full_path, path)
+        }))
+        .chain(sidecars.iter().map(|sidecar| {
+            let path = media_subdir(&sidecar.episode).join(&sidecar.path);
+            (&sidecar.episode, &sidecar.full_path, path)
+        }));
+
+    for (episode, source, path) in files {
+        let (library_dir, label) = library(episode);
+        let destination = library_dir.join(&path);
+        let conflict = destination.exists() && destination != *source;
+        conflicts += conflict as usize;
+        in_place += (destination == *source) as usize;
+
+        libraries
+            .entry(NaturalKey::new(&label))
+            .or_default()
+            .insert(&path, conflict);
+        *library_counts.entry(NaturalKey::new(&label)).or_default() += 1;
+        if !episode.is_movie {
+            *show_counts
+                .entry(NaturalKey::new(&episode.name))
+                .or_default() += 1;
+        }
+    }
+
+    let mut lines = Vec::new();
+    for (label, tree) in &libraries {
+        lines.push(format!("{}/", label.0));
+        tree.render("", &mut lines);
+    }
+
+    lines.push(String::new());
+    lines.push("Libraries:".to_string());
+    for (label, count) in &library_counts {
+        lines.push(format!("  {}: {} files", label.0, count));
+    }
+    if !show_counts.is_empty() {
+        lines.push("Shows:".to_string());
+        for (name, count) in &show_counts {
+            lines.push(format!("  {}: {} files", name.0, count));
+        }
+    }
+    let total: usize = library_counts.values().sum();
+    lines.push("Actions:".to_string());
+    lines.push(format!("  to move: {}", total - conflicts - in_place));
+    lines.push(format!("  conflicts: {}", conflicts));
+    if in_place > 0 {
+        lines.push(format!("  in place: {}", in_place));
+    }
+
+    lines
+}
+
 pub fn dry_run_sort(
-    episodes: &Vec<Episode>,
-    subtitles: &Vec<Subtitle>,
-    tv_template: String,
-    movie_template: String,
+    episodes: &[Episode],
+    subtitles: &[Subtitle],
+    sidecars: &[SidecarFile],
+    layout: SubtitleLayout,
+    library: impl Fn(&Episode) -> (PathBuf, String),
 ) -> Result<()> {
     if episodes.is_empty() {
         bail!("No media files found in the input directory");
     }
-    let dry_map: Arc<
-        Mutex<HashMap<String, HashMap<String, HashMap<String, Vec<(String, bool)>>>>>,
-    > = Arc::new(Mutex::new(HashMap::new()));
 
-    episodes.par_iter().for_each(|episode| {
-        let media_name = if episode.is_movie {
-            movie_template.clone()
-        } else {
-            tv_template.clone()
+    for line in dry_run_tree(episodes, subtitles, sidecars, layout, library) {
+        println!("{}", line);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::archive::ArchiveKind;
+    use crate::config::route::Route;
+
+    #[test]
+    fn test_in_place_destinations() {
+        let route = |output: &str| Route {
+            output: Some(output.into()),
+            ..Default::default()
         };
-        if episode.is_movie {
-            let movie_file = format!("{}.{}", episode.name, episode.extension);
-            dry_map
-                .lock()
-                .unwrap()
-                .entry(media_name.clone())
-                .or_insert_with(HashMap::new)
-                .entry("".to_string())
-                .or_insert_with(HashMap::new)
-                .entry("".to_string())
-                .or_insert_with(Vec::new)
-                .push((movie_file, false));
-        } else {
-            let series_name = &episode.name;
-            let season_key = format!(
-                "S{:02}",
-                if episode.season == 0 {
-                    1
-                } else {
-                    episode.season
-                }
-            );
-            let episode_key = if episode.episode > 100 {
-                format!(
-                    "{} - E{:03}.{}",
-                    episode.name, episode.episode, episode.extension
-                )
-            } else {
-                format!(
-                    "{} - E{:02}.{}",
-                    episode.name, episode.episode, episode.extension
-                )
-            };
+        let mut sort = Sort {
+            output: Some("/media/Films".into()),
+            movies_output: Some("/media/Films".into()),
+            series_output: Some("/media/Series".into()),
+            routes: vec![
+                route("/media/Films/UHD"),
+                route("/kids"),
+                Route {
+                    template: Some("{resolution}".to_string()),
+                    ..Default::default()
+                },
+            ],
+            in_place: true,
+            ..Default::default()
+        };
+        sort.keep_destinations_in_output();
 
-            dry_map
-                .lock()
-                .unwrap()
-                .entry(media_name.clone())
-                .or_insert_with(HashMap::new)
-                .entry(series_name.clone())
-                .or_insert_with(HashMap::new)
-                .entry(season_key)
-                .or_insert_with(Vec::new)
-                .push((episode_key, false));
-        }
-    });
+        assert_eq!(sort.movies_output, Some("/media/Films".into()));
+        assert_eq!(sort.series_output, None);
+        assert_eq!(sort.routes.len(), 2);
+        assert!(sort.routes[0].output.is_some() && sort.routes[1].output.is_none());
+    }
 
-    // Add subtitles to the map
-    subtitles.par_iter().for_each(|subtitle| {
-        let media_name = if subtitle.episode.is_movie {
-            movie_template.clone()
-        } else {
-            tv_template.clone()
+    #[test]
+    fn test_dry_run_tree() {
+        let dir = std::env::temp_dir().join("mediasort_dry_run");
+        let season_dir = dir.join("Series").join("Show").join("S01");
+        std::fs::create_dir_all(&season_dir).unwrap();
+        std::fs::write(season_dir.join("Show - E99.mkv"), "").unwrap();
+
+        let episodes = vec![
+            Episode::new_test("Show.S01E100.mkv", false),
+            Episode::new_test("Show.S01E99.mkv", false),
+            Episode::new_test("Inception.2010.mkv", true),
+        ];
+        let mut subtitle = Subtitle::new(PathBuf::from("Inception.2010.en.srt")).unwrap();
+        subtitle.set_episode(episodes[2].clone());
+        subtitle.language = Some("en".to_string());
+
+        let library = |episode: &Episode| {
+            let library = if episode.is_movie { "Films" } else { "Series" };
+            (dir.join(library), library.to_string())
         };
-        if subtitle.episode.is_movie {
-            let subtitle_file = format!(
-                "{}.{}",
-                subtitle.filename_clean,
-                subtitle.full_path.extension().unwrap().to_str().unwrap()
-            );
-            dry_map
-                .lock()
-                .unwrap()
-                .entry(media_name.clone())
-                .or_insert_with(HashMap::new)
-                .entry("".to_string())
-                .or_insert_with(HashMap::new)
-                .entry("".to_string())
-                .or_insert_with(Vec::new)
-                .push((subtitle_file, true));
-        } else {
-            let series_name = &subtitle.episode.name;
-            let season_key = format!(
-                "S{:02}",
-                if subtitle.episode.season == 0 {
-                    1
-                } else {
-                    subtitle.episode.season
-                }
-            );
-            let language = subtitle
-                .language
-                .clone()
-                .unwrap_or_else(|| "Unknown".to_string());
-            let subtitle_key = format!(
-                "{} - E{:02}.{}.{}",
-                subtitle.episode.name,
-                subtitle.episode.episode,
-                language,
-                subtitle.full_path.extension().unwrap().to_str().unwrap()
-            );
+        let lines = dry_run_tree(
+            &episodes,
+            &[subtitle.clone()],
+            &[],
+            SubtitleLayout::Folder,
+            library,
+        );
+        let reversed: Vec<Episode> = episodes.iter().rev().cloned().collect();
+        let same_lines = dry_run_tree(&reversed, &[subtitle], &[], SubtitleLayout::Folder, library);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(lines, same_lines);
+        assert_eq!(
+            lines,
+            vec![
+                "Films/",
+                "├─ Subtitles/",
+                "│  └─ Inception (2010).en.srt",
+                "└─ Inception (2010).mkv",
+                "Series/",
+                "└─ Show/",
+                "   └─ S01/",
+                "      ├─ Show - E99.mkv  [conflict: already exists]",
+                "      └─ Show - E100.mkv",
+                "",
+                "Libraries:",
+                "  Films: 2 files",
+                "  Series: 2 files",
+                "Shows:",
+                "  Show: 2 files",
+                "Actions:",
+                "  to move: 3",
+                "  conflicts: 1",
+            ]
+        );
+    }
 
-            dry_map
-                .lock()
-                .unwrap()
-                .entry(media_name.clone())
-                .or_insert_with(HashMap::new)
-                .entry(series_name.clone())
-                .or_insert_with(HashMap::new)
-                .entry(season_key)
-                .or_insert_with(Vec::new)
-                .push((subtitle_key, true));
+    #[test]
+    fn test_sidecar_subtitles() {
+        let episodes = vec![Episode::new_test("Show.S01E02.mkv", false)];
+        let mut subtitles = Vec::new();
+        for (name, language, number) in [
+            ("Show.S01E02.en.srt", Some("en"), None),
+            ("Show.S01E02.en.cc.srt", Some("en"), Some(2)),
+            ("Show.S01E02.ass", None, None),
+        ] {
+            let mut subtitle = Subtitle::new(PathBuf::from(name)).unwrap();
+            subtitle.set_episode(episodes[0].clone());
+            subtitle.language = language.map(str::to_string);
+            subtitle.cc = name.contains(".cc.");
+            subtitle.number = number;
+            subtitles.push(subtitle);
         }
-    });
 
-    // Print the updated tree
-    print_tree(&dry_map.lock().unwrap(), "", true);
-    Ok(())
+        let lines = dry_run_tree(&episodes, &subtitles, &[], SubtitleLayout::Sidecar, |_| {
+            (PathBuf::from("Series"), "Series".to_string())
+        });
+        assert_eq!(
+            lines[..5],
+            [
+                "Series/",
+                "└─ Show/",
+                "   └─ S01/",
+                "      ├─ Show - E02.2.en.sdh.srt",
+                "      ├─ Show - E02.ass",
+            ]
+        );
+        assert_eq!(
+            lines[5..7],
+            ["      ├─ Show - E02.en.srt", "      └─ Show - E02.mkv"]
+        );
+    }
+
+    #[test]
+    fn test_keep_original_subtitle() {
+        let dir = std::env::temp_dir().join("mediasort_keep_original");
+        let library = dir.join("Show");
+        std::fs::create_dir_all(&library).unwrap();
+        let from = dir.join("Show.S01E01.srt");
+        std::fs::write(&from, "1\n00:00:01,000 --> 00:00:02,000\nHello\n\n").unwrap();
+        std::fs::write(library.join("Show - E01.srt"), "").unwrap();
+
+        let mut subtitle = Subtitle::new(from.clone()).unwrap();
+        subtitle.format = Some(SubtitleFormat::Vtt);
+        let sort = Sort {
+            keep_original_subtitles: true,
+            ..Default::default()
+        };
+        let to = library.join("Show - E01.vtt");
+        let error = sort.move_subtitle(&subtitle, &to).unwrap_err();
+        let (written, kept) = (to.exists(), from.exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(error
+            .to_string()
+            .contains("Cannot keep the original subtitle"));
+        assert!(!written && kept);
+    }
+
+    #[test]
+    fn test_sorted_sources() {
+        let row = |name: &str, action: Action| {
+            ReportRow::from_episode(
+                &Episode::new_test(name, false),
+                action,
+                None,
+                Duration::ZERO,
+            )
+        };
+        let rows = vec![
+            row("Show.S01E01.mkv", Action::Moved),
+            row("Show.S01E02.mkv", Action::Skipped).with_skip(Skip::InPlace),
+            row("Show.S01E03.mkv", Action::Skipped).with_reason("already in place"),
+            row("Show.S01E04.mkv", Action::Conflict),
+        ];
+        let sorted = sorted_sources(&rows);
+        assert_eq!(sorted.len(), 2);
+        assert!(sorted.contains(Path::new("Show.S01E02.mkv")));
+    }
+
+    #[test]
+    fn test_archives_to_delete() {
+        let archive = |name: &str| Archive {
+            path: PathBuf::from(format!("{}.zip", name)),
+            volumes: vec![PathBuf::from(format!("{}.zip", name))],
+            kind: ArchiveKind::Zip,
+        };
+        let archives = vec![
+            (archive("Movie.2010"), PathBuf::from("extracted/Movie.2010")),
+            (archive("Show.S01"), PathBuf::from("extracted/Show.S01")),
+            (archive("Show.S02"), PathBuf::from("extracted/Show.S02")),
+        ];
+        let row = |path: &str, is_movie: bool| {
+            let mut episode = Episode::new_test(path, is_movie);
+            episode.full_path = PathBuf::from(path);
+            ReportRow::from_episode(&episode, Action::Skipped, None, Duration::ZERO)
+        };
+        let rows = vec![
+            row("extracted/Movie.2010/Movie.2010.mkv", true).with_skip(Skip::DryRun),
+            row("extracted/Show.S01/Show.S01E01.mkv", false).with_reason("dry run"),
+            row("extracted/Show.S02/Show.S02E01.mkv", false).with_skip(Skip::DryRun),
+            ReportRow {
+                media_type: Some(FileType::Subtitle),
+                action: Action::Conflict,
+                ..row("extracted/Show.S02/Show.S02E01.en.srt", false)
+            },
+        ];
+        let sort = Sort {
+            delete_archives: true,
+            ..Default::default()
+        };
+
+        let deleted = sort.archives_to_delete(&archives, &rows);
+        assert_eq!(deleted, vec![&archives[0].0, &archives[2].0]);
+    }
+
+    #[test]
+    fn test_same_name_movies() {
+        let dir = std::env::temp_dir().join("mediasort_same_name_movies");
+        let films = dir.join("Films");
+        std::fs::create_dir_all(&films).unwrap();
+        std::fs::write(films.join("The Thing (1982).mkv"), "").unwrap();
+        let path = dir.join("The.Thing.2011.1080p.mkv");
+        std::fs::write(&path, "").unwrap();
+
+        let sort = Sort {
+            movies_output: Some(films.clone()),
+            upgrade: true,
+            ..Default::default()
+        };
+        let mut episodes = vec![Episode::new(&path, None).unwrap()];
+        let destination = sort.get_destination(&episodes[0]);
+        let duplicates = sort.take_duplicates(&mut episodes).unwrap();
+        let upgrade = sort.find_upgrade(&episodes[0], &destination).unwrap();
+        let library = Episode::new(&films.join("The Thing (1982).mkv"), None).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(destination, films.join("The Thing (2011).mkv"));
+        assert!(duplicates.is_empty() && upgrade.is_none());
+        assert_eq!(episodes.len(), 1);
+        assert_eq!(
+            (library.name.as_str(), library.year),
+            ("The Thing", Some(1982))
+        );
+    }
+
+    #[test]
+    fn test_movie_with_extras() {
+        let sort = Sort {
+            movies_output: Some("/media/Films".into()),
+            ..Default::default()
+        };
+        let mut episodes = vec![
+            Episode::new_test("Inception.2010.1080p.mkv", true),
+            Episode::new_test("Inception.2010.Trailer.mkv", true),
+            Episode::new_test("Tenet.2020.mkv", true),
+        ];
+        sort.match_extras(&mut episodes);
+
+        let destinations: Vec<PathBuf> = episodes
+            .iter()
+            .map(|episode| sort.get_destination(episode))
+            .collect();
+        let movie_dir = PathBuf::from("/media/Films/Inception (2010)");
+        assert_eq!(destinations[0], movie_dir.join("Inception (2010).mkv"));
+        assert_eq!(
+            destinations[1],
+            movie_dir
+                .join("Trailers")
+                .join("Inception.2010.Trailer.mkv")
+        );
+        assert_eq!(
+            destinations[2],
+            PathBuf::from("/media/Films/Tenet (2020).mkv")
+        );
+    }
 }
@@ -0,0 +1,56 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use directories::BaseDirs;
+use serde::{Deserialize, Serialize};
+
+use crate::config::profile::{Locations, ProfileFlags};
+use crate::config::route::Route;
+
+/// Global settings read from `config.toml`, applied before any profile.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct GlobalConfig {
+    pub inputs: Vec<PathBuf>,
+    pub output: Option<PathBuf>,
+    pub movies_output: Option<PathBuf>,
+    pub series_output: Option<PathBuf>,
+    pub routes: Vec<Route>,
+    pub flags: ProfileFlags,
+}
+
+/// Path of the global config file, e.g. `~/.config/MediaSort/config.toml` on Linux.
+pub fn get_config_path() -> Result<PathBuf> {
+    let base_dirs = BaseDirs::new().context("Could not get base directories")?;
+
+    Ok(base_dirs.config_dir().join("MediaSort").join("config.toml"))
+}
+
+impl GlobalConfig {
+    /// Read the global config, or an empty one if the file does not exist.
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let config_str = fs::read_to_string(path)
+            .with_context(|| format!("Could not read config {:?}", path))?;
+
+        let config: Self =
+            toml::from_str(&config_str).with_context(|| format!("Invalid config {:?}", path))?;
+        config.locations().validate()?;
+
+        Ok(config)
+    }
+
+    pub fn locations(&self) -> Locations {
+        Locations {
+            inputs: self.inputs.clone(),
+            output: self.output.clone(),
+            movies_output: self.movies_output.clone(),
+            series_output: self.series_output.clone(),
+            routes: self.routes.clone(),
+        }
+    }
+}
@@ -0,0 +1,4 @@
+pub(crate) mod global;
+pub(crate) mod profile;
+pub(crate) mod resolve;
+pub(crate) mod route;
@@ -0,0 +1,463 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::config::route::Route;
+use crate::convert::SubtitleFormat;
+use crate::duplicate::KeepPolicy;
+use crate::extra::SamplePolicy;
+use crate::quality::QualityProfile;
+use crate::sidecar::JunkPolicy;
+use crate::subtitle::SubtitleLayout;
+
+/// Current version of the profile file format.
+pub const PROFILE_VERSION: u32 = 2;
+
+/// A profile as stored on disk in a `.pms` file.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct ProfileConfig {
+    pub version: u32,
+    pub name: String,
+    pub inputs: Vec<PathBuf>,
+    pub output: PathBuf,
+    /// Output root for movies, instead of `{output}/{movie-template}`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub movies_output: Option<PathBuf>,
+    /// Output root for series, instead of `{output}/{tv-template}`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub series_output: Option<PathBuf>,
+    /// Routing rules, the first matching rule picks the output root and template.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub routes: Vec<Route>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Name of a parent profile whose flags are inherited.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extends: Option<String>,
+    #[serde(default)]
+    pub flags: ProfileFlags,
+}
+
+/// Input and output locations set by one settings layer. Unset locations are inherited.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Locations {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub inputs: Vec<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub movies_output: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub series_output: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub routes: Vec<Route>,
+}
+
+impl Locations {
+    /// Names of every location setting.
+    pub const KEYS: [&'static str; 5] = [
+        "inputs",
+        "output",
+        "movies-output",
+        "series-output",
+        "routes",
+    ];
+
+    pub fn validate(&self) -> Result<()> {
+        for route in &self.routes {
+            route.validate()?;
+        }
+        Ok(())
+    }
+}
+
+/// Flags stored in a profile or in the global config. Flags left out are inherited from
+/// the layers below (see `config::resolve`).
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct ProfileFlags {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub verbose: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recursive: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub threads: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub webhook: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dry_run: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tv_template: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub movie_template: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub search: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub skip_subtitles: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keep: Option<KeepPolicy>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trash: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub upgrade: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quality: Option<QualityProfile>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subtitle_layout: Option<SubtitleLayout>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subtitle_format: Option<SubtitleFormat>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub utf8_subtitles: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keep_original_subtitles: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub extract_subtitles: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub skip_sidecars: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub junk: Option<JunkPolicy>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub samples: Option<SamplePolicy>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sample_size: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sample_duration: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub extract_archives: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub delete_archives: Option<bool>,
+}
+
+/// Every flag with a value, once all layers have been applied.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct ResolvedFlags {
+    pub verbose: bool,
+    pub recursive: bool,
+    pub threads: usize,
+    pub webhook: String,
+    pub dry_run: bool,
+    pub tv_template: String,
+    pub movie_template: String,
+    pub search: bool,
+    pub skip_subtitles: bool,
+    /// Which copy is sorted when the same media is found several times.
+    pub keep: KeepPolicy,
+    /// Folder for the discarded duplicates, left in the input when unset.
+    pub trash: Option<PathBuf>,
+    /// Replace the library copy when the new one is better according to `quality`.
+    pub upgrade: bool,
+    pub quality: QualityProfile,
+    /// Subtitles in a `Subtitles` folder, or next to their media.
+    pub subtitle_layout: SubtitleLayout,
+    /// Convert the text subtitles to SRT or VTT, kept as they are when unset.
+    pub subtitle_format: Option<SubtitleFormat>,
+    /// Re-encode the text subtitles to UTF-8.
+    pub utf8_subtitles: bool,
+    /// Keep the original next to a converted subtitle.
+    pub keep_original_subtitles: bool,
+    /// Write the embedded text subtitles of the medias as sidecars.
+    pub extract_subtitles: bool,
+    /// Leave the files sharing the base name of a media in the input, e.g. `.nfo`.
+    pub skip_sidecars: bool,
+    /// Keep or delete the junk files found next to the medias, e.g. `RARBG.txt`.
+    pub junk: JunkPolicy,
+    /// Skip or delete the samples.
+    pub samples: SamplePolicy,
+    /// Videos under this size in MB are samples when their duration is unknown, 0 is off.
+    pub sample_size: u64,
+    /// Videos shorter than this many seconds are samples, 0 is off.
+    pub sample_duration: u64,
+    /// Extract the `.zip` and `.rar` archives of the inputs and sort their content.
+    pub extract_archives: bool,
+    /// Delete an extracted archive set once its medias are sorted.
+    pub delete_archives: bool,
+}
+
+impl Default for ResolvedFlags {
+    fn default() -> Self {
+        Self {
+            verbose: false,
+            recursive: false,
+            threads: num_cpus::get().saturating_sub(2).max(1),
+            webhook: "default".to_string(),
+            dry_run: false,
+            tv_template: "Series".to_string(),
+            movie_template: "Films".to_string(),
+            search: false,
+            skip_subtitles: false,
+            keep: KeepPolicy::Best,
+            trash: None,
+            upgrade: false,
+            quality: QualityProfile::default(),
+            subtitle_layout: SubtitleLayout::Folder,
+            subtitle_format: None,
+            utf8_subtitles: false,
+            keep_original_subtitles: false,
+            extract_subtitles: false,
+            skip_sidecars: false,
+            junk: JunkPolicy::Keep,
+            samples: SamplePolicy::Skip,
+            sample_size: 0,
+            sample_duration: 0,
+            extract_archives: false,
+            delete_archives: false,
+        }
+    }
+}
+
+impl ResolvedFlags {
+    /// Names of every known flag, as written in profiles and config files.
+    pub fn keys() -> Vec<String> {
+        to_map(&Self::default()).keys().cloned().collect()
+    }
+}
+
+impl ProfileFlags {
+    /// Set a single flag from a `key=value` string, checking both the key and the value type.
+    pub fn set_from_str(&mut self, flag: &str) -> Result<()> {
+        let mut parts = flag.splitn(2, '=');
+        let key = parts.next().context("Flag has no key")?.trim();
+        let value = parts.next().context("Flag has no value")?.trim();
+
+        if !ResolvedFlags::keys().iter().any(|known| known == key) {
+            bail!(
+                "Unknown flag {:?}, expected one of: {}",
+                key,
+                ResolvedFlags::keys().join(", ")
+            );
+        }
+
+        let mut flags = to_map(&*self);
+        flags.insert(key.to_string(), parse_setting_value(key, value)?);
+
+        *self = serde_json::from_value(Value::Object(flags))
+            .with_context(|| format!("Invalid value {:?} for flag {:?}", value, key))?;
+
+        Ok(())
+    }
+
+    /// Fill the flags not set here from a parent profile's flags.
+    pub fn inherit(&mut self, parent: &ProfileFlags) -> Result<()> {
+        let mut flags = to_map(parent);
+        flags.extend(to_map(&*self));
+        *self = serde_json::from_value(Value::Object(flags))?;
+        Ok(())
+    }
+}
+
+impl ProfileConfig {
+    pub fn new(name: &str, inputs: Vec<PathBuf>, output: PathBuf) -> Self {
+        Self {
+            version: PROFILE_VERSION,
+            name: name.to_string(),
+            inputs,
+            output,
+            movies_output: None,
+            series_output: None,
+            routes: Vec::new(),
+            description: None,
+            extends: None,
+            flags: ProfileFlags::default(),
+        }
+    }
+
+    /// Read a profile from disk, migrating it to the current version if needed.
+    pub fn load(path: &Path) -> Result<Self> {
+        let profile_str = fs::read_to_string(path)
+            .with_context(|| format!("Could not read profile {:?}", path))?;
+        let profile: Value = serde_json::from_str(&profile_str)
+            .with_context(|| format!("Profile {:?} is not valid JSON", path))?;
+
+        Self::from_value(profile).with_context(|| format!("Invalid profile {:?}", path))
+    }
+
+    pub fn from_value(profile: Value) -> Result<Self> {
+        let profile: Self = serde_json::from_value(migrate(profile)?)?;
+        profile.locations().validate()?;
+        Ok(profile)
+    }
+
+    pub fn locations(&self) -> Locations {
+        Locations {
+            inputs: self.inputs.clone(),
+            output: Some(self.output.clone()),
+            movies_output: self.movies_output.clone(),
+            series_output: self.series_output.clone(),
+            routes: self.routes.clone(),
+        }
+    }
+
+    /// Rewrite every path of the profile, e.g. when moving it to another machine.
+    pub fn map_paths(&mut self, f: impl Fn(&Path) -> PathBuf) {
+        for input in self.inputs.iter_mut() {
+            *input = f(input);
+        }
+        self.output = f(&self.output);
+        self.movies_output = self.movies_output.as_deref().map(&f);
+        self.series_output = self.series_output.as_deref().map(&f);
+        self.flags.trash = self.flags.trash.as_deref().map(&f);
+        for route in self.routes.iter_mut() {
+            route.input = route.input.as_deref().map(&f);
+            route.output = route.output.as_deref().map(&f);
+        }
+    }
+
+    /// Every directory the profile reads from or sorts to.
+    pub fn directories(&self) -> Vec<&PathBuf> {
+        let mut directories: Vec<&PathBuf> = self.inputs.iter().collect();
+        directories.push(&self.output);
+        directories.extend(self.movies_output.iter());
+        directories.extend(self.series_output.iter());
+        directories.extend(self.routes.iter().filter_map(|route| route.output.as_ref()));
+        directories
+    }
+
+    /// Set a location from its string form, as given to `profile edit`.
+    pub fn set_location(&mut self, key: &str, value: &str) -> Result<()> {
+        let mut locations = to_map(&self.locations());
+        if value.is_empty() {
+            locations.remove(key);
+        } else {
+            locations.insert(key.to_string(), parse_setting_value(key, value)?);
+        }
+
+        let locations: Locations = serde_json::from_value(Value::Object(locations))
+            .with_context(|| format!("Invalid value {:?} for {:?}", value, key))?;
+        locations.validate()?;
+
+        if locations.inputs.is_empty() {
+            bail!("A profile needs at least one input");
+        }
+        self.inputs = locations.inputs;
+        self.output = locations.output.context("A profile needs an output")?;
+        self.movies_output = locations.movies_output;
+        self.series_output = locations.series_output;
+        self.routes = locations.routes;
+
+        Ok(())
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let profile_str = serde_json::to_string_pretty(self)?;
+        fs::write(path, profile_str)
+            .with_context(|| format!("Could not write profile {:?}", path))?;
+        Ok(())
+    }
+}
+
+/// Parse a flag value given as a string with the type of the flag in `ProfileFlags`, so a
+/// `tv-template` of `2024` stays a string while `threads=4` is a number.
+fn parse_flag_value(key: &str, value: &str) -> Result<Value> {
+    let candidates = [
+        Some(Value::String(value.to_string())),
+        value.parse::<bool>().ok().map(Value::Bool),
+        value
+            .parse::<u64>()
+            .ok()
+            .map(|number| Value::Number(number.into())),
+    ];
+    candidates
+        .into_iter()
+        .flatten()
+        .find(|candidate| {
+            let flag = Map::from_iter([(key.to_string(), candidate.clone())]);
+            serde_json::from_value::<ProfileFlags>(Value::Object(flag)).is_ok()
+        })
+        .with_context(|| format!("Invalid value {:?} for flag {:?}", value, key))
+}
+
+/// Parse any setting given as a string, e.g. from the environment or `profile edit`.
+/// `inputs` is a list of paths split like `PATH`, `routes` and `quality` are JSON.
+pub fn parse_setting_value(key: &str, value: &str) -> Result<Value> {
+    match key {
+        "inputs" => Ok(Value::Array(
+            env::split_paths(value)
+                .map(|path| Value::String(path.to_string_lossy().to_string()))
+                .collect(),
+        )),
+        "routes" => serde_json::from_str(value).context("Routes must be a JSON array"),
+        "quality" => serde_json::from_str(value).context("Quality must be a JSON object"),
+        "output" | "movies-output" | "series-output" => Ok(Value::String(value.to_string())),
+        _ => parse_flag_value(key, value),
+    }
+}
+
+/// Serialize a config struct into a JSON object, keeping only the keys that are set.
+pub fn to_map<T: Serialize>(value: &T) -> Map<String, Value> {
+    match serde_json::to_value(value) {
+        Ok(Value::Object(map)) => map,
+        _ => Map::new(),
+    }
+}
+
+/// Bring a raw profile up to `PROFILE_VERSION`, one version at a time.
+fn migrate(mut profile: Value) -> Result<Value> {
+    let mut version = match profile.get("version") {
+        Some(version) => version
+            .as_u64()
+            .context("Profile version is not a number")? as u32,
+        None => 0,
+    };
+
+    if version > PROFILE_VERSION {
+        bail!(
+            "Profile version {} is newer than the supported version {}",
+            version,
+            PROFILE_VERSION
+        );
+    }
+
+    while version < PROFILE_VERSION {
+        let object = profile
+            .as_object_mut()
+            .context("Profile is not a JSON object")?;
+        match version {
+            0 => migrate_v0(object),
+            1 => migrate_v1(object),
+            _ => unreachable!(),
+        }
+        version += 1;
+        object.insert("version".to_string(), Value::from(version));
+    }
+
+    Ok(profile)
+}
+
+/// Version 0 profiles were written without a schema: `profile edit` could store any key,
+/// so unknown keys are dropped instead of being rejected.
+fn migrate_v0(profile: &mut Map<String, Value>) {
+    const KNOWN_KEYS: [&str; 5] = ["name", "input", "output", "description", "flags"];
+
+    profile.retain(|key, _| {
+        let known = KNOWN_KEYS.contains(&key.as_str());
+        if !known {
+            eprintln!("Dropping unknown profile key {:?}", key);
+        }
+        known
+    });
+
+    let known_flags = ResolvedFlags::keys();
+    if let Some(Value::Object(flags)) = profile.get_mut("flags") {
+        flags.retain(|key, _| {
+            let known = known_flags.contains(key);
+            if !known {
+                eprintln!("Dropping unknown profile flag {:?}", key);
+            }
+            known
+        });
+    }
+}
+
+/// Version 1 profiles had a single `input` directory.
+fn migrate_v1(profile: &mut Map<String, Value>) {
+    if let Some(input) = profile.remove("input") {
+        profile.insert("inputs".to_string(), Value::Array(vec![input]));
+    }
+}
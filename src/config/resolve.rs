@@ -0,0 +1,155 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt::{self, Display, Formatter};
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use serde_json::{Map, Value};
+
+use crate::config::global::{get_config_path, GlobalConfig};
+use crate::config::profile::{
+    parse_setting_value, to_map, Locations, ProfileConfig, ProfileFlags, ResolvedFlags,
+};
+
+/// Prefix of the environment variables that override settings, e.g. `MEDIASORT_DRY_RUN`.
+const ENV_PREFIX: &str = "MEDIASORT_";
+
+/// Where a resolved setting comes from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Source {
+    Default,
+    Config(PathBuf),
+    Profile(String),
+    Env(String),
+    Cli,
+}
+
+impl Display for Source {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Default => write!(f, "default"),
+            Source::Config(path) => write!(f, "config {}", path.display()),
+            Source::Profile(name) => write!(f, "profile {:?}", name),
+            Source::Env(var) => write!(f, "env {}", var),
+            Source::Cli => write!(f, "command line"),
+        }
+    }
+}
+
+/// Settings after applying, in order: built-in defaults, the global config, the profile,
+/// environment variables and explicit command line flags.
+#[derive(Debug, Clone)]
+pub struct ResolvedConfig {
+    pub locations: Locations,
+    pub flags: ResolvedFlags,
+    pub values: Map<String, Value>,
+    pub sources: BTreeMap<String, Source>,
+}
+
+/// Resolve every setting for a run. `cli` only holds the flags given explicitly.
+pub fn resolve(profile: Option<&ProfileConfig>, cli: Map<String, Value>) -> Result<ResolvedConfig> {
+    resolve_from(get_config_path()?, |var| env::var(var).ok(), profile, cli)
+}
+
+/// Resolve every setting from the global config at `config_path` and the environment
+/// variables read by `env_var`.
+pub fn resolve_from(
+    config_path: PathBuf,
+    env_var: impl Fn(&str) -> Option<String>,
+    profile: Option<&ProfileConfig>,
+    cli: Map<String, Value>,
+) -> Result<ResolvedConfig> {
+    let mut resolver = Resolver::new();
+
+    let config = GlobalConfig::load(&config_path)?;
+    resolver.layer(
+        Source::Config(config_path),
+        to_layer(&config.locations(), &config.flags),
+    )?;
+
+    if let Some(profile) = profile {
+        resolver.layer(
+            Source::Profile(profile.name.clone()),
+            to_layer(&profile.locations(), &profile.flags),
+        )?;
+    }
+
+    for key in setting_keys() {
+        let var = format!("{}{}", ENV_PREFIX, key.to_uppercase().replace('-', "_"));
+        if let Some(value) = env_var(&var) {
+            let mut layer = Map::new();
+            layer.insert(key.clone(), parse_setting_value(&key, &value)?);
+            resolver.layer(Source::Env(var), layer)?;
+        }
+    }
+
+    resolver.layer(Source::Cli, cli)?;
+
+    resolver.finish()
+}
+
+/// Every setting key, as used in config files, profiles and environment variables.
+pub fn setting_keys() -> Vec<String> {
+    let mut keys: Vec<String> = Locations::KEYS.iter().map(|key| key.to_string()).collect();
+    keys.extend(ResolvedFlags::keys());
+    keys
+}
+
+fn to_layer(locations: &Locations, flags: &ProfileFlags) -> Map<String, Value> {
+    let mut layer = to_map(locations);
+    layer.extend(to_map(flags));
+    layer
+}
+
+struct Resolver {
+    values: Map<String, Value>,
+    sources: BTreeMap<String, Source>,
+}
+
+impl Resolver {
+    fn new() -> Self {
+        let values = to_map(&ResolvedFlags::default());
+        let sources = values
+            .keys()
+            .map(|key| (key.clone(), Source::Default))
+            .collect();
+
+        Self { values, sources }
+    }
+
+    /// Apply a layer on top of the current values, checking its keys and value types first.
+    fn layer(&mut self, source: Source, layer: Map<String, Value>) -> Result<()> {
+        let (locations, flags): (Map<String, Value>, Map<String, Value>) = layer
+            .clone()
+            .into_iter()
+            .partition(|(key, _)| Locations::KEYS.contains(&key.as_str()));
+
+        serde_json::from_value::<Locations>(Value::Object(locations))
+            .with_context(|| format!("Invalid settings from {}", source))?
+            .validate()?;
+        serde_json::from_value::<ProfileFlags>(Value::Object(flags))
+            .with_context(|| format!("Invalid settings from {}", source))?;
+
+        for (key, value) in layer {
+            self.sources.insert(key.clone(), source.clone());
+            self.values.insert(key, value);
+        }
+
+        Ok(())
+    }
+
+    fn finish(self) -> Result<ResolvedConfig> {
+        let (locations, flags): (Map<String, Value>, Map<String, Value>) = self
+            .values
+            .clone()
+            .into_iter()
+            .partition(|(key, _)| Locations::KEYS.contains(&key.as_str()));
+
+        Ok(ResolvedConfig {
+            locations: serde_json::from_value(Value::Object(locations))?,
+            flags: serde_json::from_value(Value::Object(flags))?,
+            values: self.values,
+            sources: self.sources,
+        })
+    }
+}
@@ -0,0 +1,245 @@
+use std::fmt;
+use std::path::PathBuf;
+
+use anyhow::{bail, Context, Result};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::episode::Episode;
+use crate::search::result::MediaType;
+
+/// A routing rule: media matching every condition set here go to `output`, in the
+/// `template` folder. Rules are tried in order and the first matching one wins.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Route {
+    /// Name shown in verbose output and dry runs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+
+    /// Regex matched against the original file name.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pattern: Option<Pattern>,
+    /// Only match files found under this input directory.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input: Option<PathBuf>,
+    /// Only match movies or only series.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub media: Option<MediaType>,
+    /// Regex matched against the parsed show or movie name.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<Pattern>,
+    /// Only match this season.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub season: Option<u32>,
+    /// Only match media with at least this vertical resolution, e.g. 2160.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_resolution: Option<u32>,
+    /// Only match media tagged with this language, e.g. VOSTFR.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    /// Only match media with this provider genre, e.g. Animation. Needs `--search`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub genre: Option<String>,
+    /// Only match this source, e.g. BluRay or WEB-DL.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    /// Only match HDR media, or media with this HDR format, e.g. DV.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hdr: Option<String>,
+    /// Only match this release group.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+
+    /// Output root for the matching media.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<PathBuf>,
+    /// Library folder inside the output root, replacing the tv or movie template.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub template: Option<String>,
+}
+
+/// A route regex, compiled once when the config is loaded so that an invalid pattern fails
+/// there instead of never matching.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(try_from = "String", into = "String")]
+pub struct Pattern(Regex);
+
+impl Pattern {
+    pub fn new(pattern: &str) -> Result<Self> {
+        let regex =
+            Regex::new(pattern).with_context(|| format!("Invalid route pattern {:?}", pattern))?;
+        Ok(Pattern(regex))
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        self.0.is_match(text)
+    }
+
+    pub fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
+}
+
+impl TryFrom<String> for Pattern {
+    type Error = String;
+
+    fn try_from(pattern: String) -> std::result::Result<Self, Self::Error> {
+        Pattern::new(&pattern).map_err(|e| format!("{:#}", e))
+    }
+}
+
+impl From<Pattern> for String {
+    fn from(pattern: Pattern) -> Self {
+        pattern.as_str().to_string()
+    }
+}
+
+impl PartialEq for Pattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl fmt::Debug for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl Route {
+    pub fn validate(&self) -> Result<()> {
+        if self.output.is_none() && self.template.is_none() {
+            bail!("Route {} needs an output or a template", self.label());
+        }
+        Ok(())
+    }
+
+    /// Name of the rule for messages.
+    pub fn label(&self) -> String {
+        match &self.name {
+            Some(name) => format!("{:?}", name),
+            None => "<unnamed>".to_string(),
+        }
+    }
+
+    pub fn matches(&self, episode: &Episode) -> bool {
+        self.check(episode).is_ok()
+    }
+
+    /// Check every condition of the rule, returning the first one that fails.
+    pub fn check(&self, episode: &Episode) -> std::result::Result<(), String> {
+        if let Some(pattern) = &self.pattern {
+            if !pattern.is_match(&episode.filename) {
+                return Err(format!("file name does not match {:?}", pattern));
+            }
+        }
+
+        if let Some(input) = &self.input {
+            if !episode.full_path.starts_with(input) {
+                return Err(format!("not found in {:?}", input));
+            }
+        }
+
+        match self.media {
+            Some(MediaType::Movie) if !episode.is_movie => return Err("not a movie".to_string()),
+            Some(MediaType::Series) if episode.is_movie => return Err("not a series".to_string()),
+            _ => {}
+        }
+
+        if let Some(title) = &self.title {
+            if !title.is_match(&episode.name) {
+                return Err(format!(
+                    "name {:?} does not match {:?}",
+                    episode.name, title
+                ));
+            }
+        }
+
+        if let Some(season) = self.season {
+            if episode.is_movie || episode.season != season {
+                return Err(format!("not season {}", season));
+            }
+        }
+
+        if let Some(min_resolution) = self.min_resolution {
+            match episode.release.resolution {
+                Some(resolution) if resolution >= min_resolution => {}
+                Some(resolution) => {
+                    return Err(format!(
+                        "resolution {}p below {}p",
+                        resolution, min_resolution
+                    ))
+                }
+                None => return Err("unknown resolution".to_string()),
+            }
+        }
+
+        if let Some(language) = &self.language {
+            let languages = &episode.release.languages;
+            if !languages
+                .iter()
+                .any(|tag| tag.eq_ignore_ascii_case(language))
+            {
+                return Err(format!(
+                    "languages {:?} do not include {:?}",
+                    languages, language
+                ));
+            }
+        }
+
+        if let Some(genre) = &self.genre {
+            if !episode
+                .genres
+                .iter()
+                .any(|tag| tag.eq_ignore_ascii_case(genre))
+            {
+                return Err(format!(
+                    "genres {:?} do not include {:?}",
+                    episode.genres, genre
+                ));
+            }
+        }
+
+        if let Some(source) = &self.source {
+            if !matches_tag(&episode.release.source, source) {
+                return Err(format!(
+                    "source {:?} is not {:?}",
+                    episode.release.source, source
+                ));
+            }
+        }
+
+        if let Some(hdr) = &self.hdr {
+            let is_hdr = match &episode.release.hdr {
+                Some(formats) => {
+                    hdr.eq_ignore_ascii_case("HDR")
+                        || formats
+                            .split_whitespace()
+                            .any(|format| format.eq_ignore_ascii_case(hdr))
+                }
+                None => false,
+            };
+            if !is_hdr {
+                return Err(format!("HDR {:?} is not {:?}", episode.release.hdr, hdr));
+            }
+        }
+
+        if let Some(group) = &self.group {
+            if !matches_tag(&episode.release.group, group) {
+                return Err(format!(
+                    "group {:?} is not {:?}",
+                    episode.release.group, group
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn matches_tag(value: &Option<String>, expected: &str) -> bool {
+    value
+        .as_ref()
+        .is_some_and(|value| value.eq_ignore_ascii_case(expected))
+}
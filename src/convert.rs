@@ -0,0 +1,295 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::{bail, Result};
+use chardetng::EncodingDetector;
+use clap::ValueEnum;
+use encoding_rs::{Encoding, UTF_8};
+use regex::Captures;
+use serde::{Deserialize, Serialize};
+
+use crate::search::strings::{ASS_OVERRIDE, HTML_TAG};
+
+/// Text subtitle format the subtitles are converted to.
+#[derive(Serialize, Deserialize, ValueEnum, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SubtitleFormat {
+    Srt,
+    Vtt,
+}
+
+impl SubtitleFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            SubtitleFormat::Srt => "srt",
+            SubtitleFormat::Vtt => "vtt",
+        }
+    }
+}
+
+/// One subtitle line, with its times in milliseconds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cue {
+    pub start: u64,
+    pub end: u64,
+    pub text: String,
+}
+
+fn extension(path: &Path) -> String {
+    path.extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
+
+/// Whether the subtitle is text that can be re-encoded, unlike VobSub `.sub` pictures.
+pub fn is_text(path: &Path) -> bool {
+    matches!(extension(path).as_str(), "srt" | "vtt" | "ass" | "ssa")
+}
+
+/// Whether the subtitle is text in another format than `format`.
+pub fn can_convert(path: &Path, format: SubtitleFormat) -> bool {
+    is_text(path) && extension(path) != format.extension()
+}
+
+/// Text of a subtitle file, with the name of its encoding when it was not UTF-8. The
+/// encoding is taken from the byte order mark, or guessed, e.g. Windows-1252.
+pub fn decode(bytes: &[u8]) -> (String, Option<&'static str>) {
+    if let Some((encoding, bom_length)) = Encoding::for_bom(bytes) {
+        let (text, _) = encoding.decode_without_bom_handling(&bytes[bom_length..]);
+        return (
+            text.into_owned(),
+            (encoding != UTF_8).then(|| encoding.name()),
+        );
+    }
+    if let Ok(text) = std::str::from_utf8(bytes) {
+        return (text.to_string(), None);
+    }
+
+    let mut detector = EncodingDetector::new();
+    detector.feed(bytes, true);
+    let encoding = detector.guess(None, true);
+    let (text, _, _) = encoding.decode(bytes);
+    (text.into_owned(), Some(encoding.name()))
+}
+
+/// New content of a subtitle, in UTF-8 and in `format` when given. `None` when the file is
+/// already UTF-8 in the right format and can be moved as is.
+pub fn convert(path: &Path, format: Option<SubtitleFormat>) -> Result<Option<String>> {
+    let (text, encoding) = decode(&fs::read(path)?);
+
+    let Some(format) = format.filter(|&format| can_convert(path, format)) else {
+        return Ok(encoding.map(|_| text));
+    };
+    let cues = match extension(path).as_str() {
+        "ass" | "ssa" => parse_ass(&text),
+        _ => parse_srt(&text),
+    };
+    if cues.is_empty() {
+        bail!("No subtitle lines found to convert");
+    }
+
+    Ok(Some(match format {
+        SubtitleFormat::Srt => write_srt(&cues),
+        SubtitleFormat::Vtt => write_vtt(&cues),
+    }))
+}
+
+/// Cues of an SRT or VTT file. VTT styling is dropped, only `<i>`, `<b>` and `<u>` are kept.
+pub fn parse_srt(text: &str) -> Vec<Cue> {
+    let mut cues = Vec::new();
+    let mut lines = text.lines().map(str::trim_end);
+
+    while let Some(line) = lines.next() {
+        let Some((start, end)) = line.split_once("-->") else {
+            continue;
+        };
+        // VTT settings follow the end time, e.g. `align:start`
+        let end = end.split_whitespace().next().unwrap_or_default();
+        let (Some(start), Some(end)) = (parse_time(start), parse_time(end)) else {
+            continue;
+        };
+
+        let text: Vec<String> = lines
+            .by_ref()
+            .take_while(|line| !line.trim().is_empty())
+            .map(basic_tags)
+            .collect();
+        cues.push(Cue {
+            start,
+            end,
+            text: text.join("\n"),
+        });
+    }
+
+    cues
+}
+
+/// Fields of a `Dialogue` line when the script has no `Format` line.
+const DEFAULT_ASS_FORMAT: [&str; 10] = [
+    "layer", "start", "end", "style", "name", "marginl", "marginr", "marginv", "effect", "text",
+];
+
+/// Cues of the `Dialogue` lines of an ASS or SSA script, without their styling.
+pub fn parse_ass(text: &str) -> Vec<Cue> {
+    let mut format: Vec<String> = DEFAULT_ASS_FORMAT.map(String::from).to_vec();
+    let mut cues = Vec::new();
+
+    for line in text.lines().map(str::trim) {
+        if let Some(fields) = line.strip_prefix("Format:") {
+            format = fields
+                .split(',')
+                .map(|field| field.trim().to_lowercase())
+                .collect();
+            continue;
+        }
+        let Some(dialogue) = line.strip_prefix("Dialogue:") else {
+            continue;
+        };
+
+        // The text is last and may hold commas
+        let values: Vec<&str> = dialogue.splitn(format.len(), ',').collect();
+        let field = |name: &str| {
+            format
+                .iter()
+                .position(|field| field == name)
+                .and_then(|index| values.get(index))
+                .map(|value| value.trim())
+        };
+        let (Some(start), Some(end), Some(text)) = (
+            field("start").and_then(parse_time),
+            field("end").and_then(parse_time),
+            field("text"),
+        ) else {
+            continue;
+        };
+
+        let text = text
+            .replace("\\N", "\n")
+            .replace("\\n", "\n")
+            .replace("\\h", " ");
+        let text = ASS_OVERRIDE.replace_all(&text, "");
+        if !text.trim().is_empty() {
+            cues.push(Cue {
+                start,
+                end,
+                text: text.trim().to_string(),
+            });
+        }
+    }
+
+    cues.sort_by_key(|cue| cue.start);
+    cues
+}
+
+/// Keep the tags SRT players know, drop VTT classes, voices and timestamps.
+fn basic_tags(line: &str) -> String {
+    HTML_TAG
+        .replace_all(line, |captures: &Captures| {
+            match captures[1].to_lowercase().as_str() {
+                "i" | "b" | "u" => captures[0].to_lowercase(),
+                _ => String::new(),
+            }
+        })
+        .to_string()
+}
+
+/// `01:02:03,456` or `01:02:03.456` in SRT and VTT, `02:03.456` in VTT, `1:02:03.45` in ASS.
+fn parse_time(time: &str) -> Option<u64> {
+    let time = time.trim().replace(',', ".");
+    let (clock, fraction) = time.split_once('.').unwrap_or((&time, "0"));
+    let mut seconds = 0;
+    for part in clock.split(':') {
+        seconds = seconds * 60 + part.parse::<u64>().ok()?;
+    }
+    // Centiseconds in ASS, milliseconds otherwise
+    let fraction: String = fraction.chars().chain("000".chars()).take(3).collect();
+    Some(seconds * 1000 + fraction.parse::<u64>().ok()?)
+}
+
+fn format_time(time: u64, separator: char) -> String {
+    format!(
+        "{:02}:{:02}:{:02}{}{:03}",
+        time / 3_600_000,
+        time / 60_000 % 60,
+        time / 1000 % 60,
+        separator,
+        time % 1000
+    )
+}
+
+pub fn write_srt(cues: &[Cue]) -> String {
+    let mut srt = String::new();
+    for (i, cue) in cues.iter().enumerate() {
+        srt.push_str(&format!(
+            "{}\n{} --> {}\n{}\n\n",
+            i + 1,
+            format_time(cue.start, ','),
+            format_time(cue.end, ','),
+            cue.text
+        ));
+    }
+    srt
+}
+
+pub fn write_vtt(cues: &[Cue]) -> String {
+    let mut vtt = String::from("WEBVTT\n\n");
+    for cue in cues {
+        vtt.push_str(&format!(
+            "{} --> {}\n{}\n\n",
+            format_time(cue.start, '.'),
+            format_time(cue.end, '.'),
+            cue.text
+        ));
+    }
+    vtt
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_subtitle_conversion() {
+        let latin1 =
+            b"1\r\n00:00:01,000 --> 00:00:02,500\r\nD\xe9j\xe0 vu, tr\xe8s \xe9trange.\r\n";
+        let (text, encoding) = decode(latin1);
+        assert!(text.contains("Déjà vu, très étrange."));
+        assert_eq!(encoding, Some("windows-1252"));
+        assert_eq!(
+            decode("\u{feff}Déjà".as_bytes()),
+            ("Déjà".to_string(), None)
+        );
+
+        let vtt = "WEBVTT\n\nNOTE a comment\n\n00:01.000 --> 00:02.500 align:start\n<v Bob><i>Hello</i> <c.yellow>there</c>\n\n";
+        let srt = write_srt(&parse_srt(vtt));
+        assert_eq!(
+            srt,
+            "1\n00:00:01,000 --> 00:00:02,500\n<i>Hello</i> there\n\n"
+        );
+        assert_eq!(
+            write_vtt(&parse_srt(&srt)),
+            "WEBVTT\n\n00:00:01.000 --> 00:00:02.500\n<i>Hello</i> there\n\n"
+        );
+
+        let ass = "[Script Info]\nTitle: Test\n\n[Events]\n\
+                   Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\n\
+                   Dialogue: 0,0:00:04.00,0:00:05.50,Default,,0,0,0,,{\\an8}Second, line\n\
+                   Dialogue: 0,0:00:01.20,0:00:03.00,Default,,0,0,0,,First\\Nline\n";
+        let dir = std::env::temp_dir().join("mediasort_convert");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("Show.S01E01.en.ass");
+        std::fs::write(&path, ass).unwrap();
+        let converted = convert(&path, Some(SubtitleFormat::Srt)).unwrap();
+        let unchanged = convert(&path, None).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(
+            converted.as_deref(),
+            Some(
+                "1\n00:00:01,200 --> 00:00:03,000\nFirst\nline\n\n\
+                 2\n00:00:04,000 --> 00:00:05,500\nSecond, line\n\n"
+            )
+        );
+        assert_eq!(unchanged, None);
+    }
+}
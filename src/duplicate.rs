@@ -0,0 +1,293 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::episode::Episode;
+use crate::probe::{self, FileKey, MediaInfo};
+use crate::release::ReleaseInfo;
+
+/// Which copy of a media is kept when several are found.
+#[derive(Serialize, Deserialize, ValueEnum, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum KeepPolicy {
+    /// Best release: resolution, source and HDR, then bitrate and size.
+    #[default]
+    Best,
+    Largest,
+    /// Most recently modified.
+    Newest,
+    /// Only report the duplicates, every copy is sorted.
+    All,
+}
+
+/// One copy of a media, from the input or already in the library.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MediaCopy {
+    pub path: PathBuf,
+    pub in_library: bool,
+    /// Whether ffprobe could read the file.
+    pub probed: bool,
+    pub key: FileKey,
+    pub release: ReleaseInfo,
+    /// Bits per second, from the size and the probed duration.
+    pub bitrate: Option<u64>,
+}
+
+impl MediaCopy {
+    pub fn from_episode(episode: &Episode) -> Result<Self> {
+        let key = FileKey::new(&episode.full_path)?;
+        Ok(Self {
+            path: episode.full_path.clone(),
+            in_library: false,
+            probed: episode.media_info.is_some(),
+            bitrate: bitrate(&key, episode.media_info.as_ref()),
+            key,
+            release: episode.release.clone(),
+        })
+    }
+
+    /// A sorted file has no release tags left in its name, so only the probe tells its quality.
+    pub fn from_library(path: &Path) -> Result<Self> {
+        let key = FileKey::new(path)?;
+        let info = probe::probe(path).ok();
+        let mut release = ReleaseInfo::default();
+        if let Some(info) = &info {
+            release.apply_media_info(info);
+        }
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            in_library: true,
+            probed: info.is_some(),
+            bitrate: bitrate(&key, info.as_ref()),
+            key,
+            release,
+        })
+    }
+
+    /// Short description, e.g. `1080p BluRay H.265, 2.1 GB`.
+    pub fn describe(&self) -> String {
+        let tags: Vec<String> = ["resolution", "source", "codec", "hdr"]
+            .iter()
+            .filter_map(|key| self.release.field(key))
+            .collect();
+        let size = format!("{:.1} GB", self.key.size as f64 / 1e9);
+
+        if tags.is_empty() {
+            size
+        } else {
+            format!("{}, {}", tags.join(" "), size)
+        }
+    }
+}
+
+fn bitrate(key: &FileKey, info: Option<&MediaInfo>) -> Option<u64> {
+    let duration = info?.duration.filter(|&duration| duration > 0.0)?;
+    Some((key.size as f64 * 8.0 / duration) as u64)
+}
+
+/// Copies of the same episode, or of the same movie and year.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DuplicateGroup {
+    pub title: String,
+    /// Kept copy first, then the others from best to worst according to the policy.
+    pub copies: Vec<MediaCopy>,
+}
+
+impl DuplicateGroup {
+    pub fn kept(&self) -> &MediaCopy {
+        &self.copies[0]
+    }
+
+    /// Input copies that should not be sorted. Library copies are never discarded.
+    pub fn discarded(&self, policy: KeepPolicy) -> Vec<&MediaCopy> {
+        if policy == KeepPolicy::All {
+            return Vec::new();
+        }
+        self.copies
+            .iter()
+            .skip(1)
+            .filter(|copy| !copy.in_library)
+            .collect()
+    }
+}
+
+/// Identity of a media: name, season and episode for series, name and year for movies.
+fn duplicate_key(episode: &Episode) -> String {
+    if episode.is_movie {
+        format!(
+            "{} ({})",
+            episode.name.to_lowercase(),
+            episode.year.unwrap_or(0)
+        )
+    } else {
+        format!(
+            "{} S{:02}E{:02}",
+            episode.name.to_lowercase(),
+            episode.season,
+            episode.episode
+        )
+    }
+}
+
+fn title(episode: &Episode) -> String {
+    match (episode.is_movie, episode.year.filter(|&year| year > 0)) {
+        (true, Some(year)) => format!("{} ({})", episode.name, year),
+        (true, None) => episode.name.clone(),
+        (false, _) => format!(
+            "{} S{:02}E{:02}",
+            episode.name, episode.season, episode.episode
+        ),
+    }
+}
+
+/// Group the medias that are the same episode or movie, together with the copy already at
+/// their destination in the library. Only groups with more than one copy are returned.
+pub fn find_duplicates(
+    episodes: &[Episode],
+    destination: impl Fn(&Episode) -> PathBuf,
+    policy: KeepPolicy,
+) -> Result<Vec<DuplicateGroup>> {
+    let mut groups: BTreeMap<String, DuplicateGroup> = BTreeMap::new();
+
+    for episode in episodes {
+        let group = groups
+            .entry(duplicate_key(episode))
+            .or_insert_with(|| DuplicateGroup {
+                title: title(episode),
+                copies: Vec::new(),
+            });
+
+        // A media already at its destination, as with `library fix`, is the library copy
+        let library_path = destination(episode);
+        if library_path.is_file() && !group.copies.iter().any(|copy| copy.path == library_path) {
+            group.copies.push(MediaCopy::from_library(&library_path)?);
+        }
+        if episode.full_path != library_path {
+            group.copies.push(MediaCopy::from_episode(episode)?);
+        }
+    }
+
+    Ok(groups
+        .into_values()
+        .filter(|group| group.copies.len() > 1)
+        .map(|mut group| {
+            sort_copies(&mut group.copies, policy);
+            group
+        })
+        .collect())
+}
+
+/// Best copy first. On a tie the library copy wins, so nothing is moved for nothing.
+fn sort_copies(copies: &mut [MediaCopy], policy: KeepPolicy) {
+    let quality = |copy: &MediaCopy| (copy.release.rank(), copy.bitrate, copy.key.size);
+
+    copies.sort_by(|a, b| {
+        let order = match policy {
+            KeepPolicy::Best | KeepPolicy::All => quality(b).cmp(&quality(a)),
+            KeepPolicy::Largest => b.key.size.cmp(&a.key.size),
+            KeepPolicy::Newest => b.key.mtime.cmp(&a.key.mtime),
+        };
+        order
+            .then_with(|| b.in_library.cmp(&a.in_library))
+            .then_with(|| a.path.cmp(&b.path))
+    });
+}
+
+/// Free path for a discarded file in the trash folder, numbered when the name is taken.
+pub fn trash_path(trash: &Path, path: &Path) -> PathBuf {
+    let file_name = path.file_name().unwrap_or_default();
+    let mut to_path = trash.join(file_name);
+    let mut n = 1;
+    while to_path.exists() {
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        to_path = match path.extension() {
+            Some(extension) => {
+                trash.join(format!("{} ({}).{}", stem, n, extension.to_string_lossy()))
+            }
+            None => trash.join(format!("{} ({})", stem, n)),
+        };
+        n += 1;
+    }
+    to_path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_duplicates() {
+        let dir = std::env::temp_dir().join("mediasort_duplicates");
+        let library = dir.join("Series");
+        std::fs::create_dir_all(&library).unwrap();
+        let mut episodes = Vec::new();
+        for (name, size) in [
+            ("Show.S01E02.720p.HDTV.mkv", 300),
+            ("Show.S01E02.1080p.BluRay.mkv", 200),
+            ("Show.S01E03.1080p.mkv", 100),
+        ] {
+            let path = dir.join(name);
+            std::fs::write(&path, vec![0u8; size]).unwrap();
+            let mut episode = Episode::new_test(name, false);
+            episode.full_path = path;
+            episodes.push(episode);
+        }
+        std::fs::write(library.join("Show - E03.mkv"), vec![0u8; 50]).unwrap();
+        let destination =
+            |episode: &Episode| library.join(format!("Show - E{:02}.mkv", episode.episode));
+
+        let groups = find_duplicates(&episodes, destination, KeepPolicy::Best).unwrap();
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].title, "Show S01E02");
+        assert_eq!(
+            groups[0].kept().path,
+            dir.join("Show.S01E02.1080p.BluRay.mkv")
+        );
+        let discarded = groups[0].discarded(KeepPolicy::Best);
+        assert_eq!(discarded.len(), 1);
+        assert_eq!(discarded[0].path, dir.join("Show.S01E02.720p.HDTV.mkv"));
+        assert!(groups[1].copies.iter().any(|copy| copy.in_library));
+        assert!(groups[1].discarded(KeepPolicy::Best).is_empty());
+        assert!(groups[0].discarded(KeepPolicy::All).is_empty());
+
+        let groups = find_duplicates(&episodes, destination, KeepPolicy::Largest).unwrap();
+        assert_eq!(groups[0].kept().path, dir.join("Show.S01E02.720p.HDTV.mkv"));
+
+        let trash = dir.join("Trash");
+        std::fs::create_dir_all(&trash).unwrap();
+        std::fs::write(trash.join("Show.S01E03.1080p.mkv"), "").unwrap();
+        let trashed = trash_path(&trash, &episodes[2].full_path);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(trashed, trash.join("Show.S01E03.1080p (1).mkv"));
+    }
+
+    #[test]
+    fn test_movie_duplicates() {
+        let dir = std::env::temp_dir().join("mediasort_movie_duplicates");
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut episodes = Vec::new();
+        for name in [
+            "The.Thing.1982.720p.mkv",
+            "The.Thing.1982.1080p.BluRay.mkv",
+            "The.Thing.2011.1080p.mkv",
+        ] {
+            let path = dir.join(name);
+            std::fs::write(&path, "").unwrap();
+            episodes.push(Episode::new(&path, None).unwrap());
+        }
+        let destination = |episode: &Episode| dir.join("Films").join(&episode.filename);
+
+        let groups = find_duplicates(&episodes, destination, KeepPolicy::Best).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].title, "The Thing (1982)");
+        assert_eq!(
+            groups[0].kept().path,
+            dir.join("The.Thing.1982.1080p.BluRay.mkv")
+        );
+    }
+}
@@ -1,10 +1,12 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use anyhow::{bail, Result};
-
-use ffprobe::ffprobe;
 use regex::Regex;
 
+use crate::error::ParseError;
+use crate::extra::{self, ExtraKind};
+use crate::probe::{self, MediaInfo};
+use crate::release::ReleaseInfo;
+use crate::search::result::SearchMatch;
 use crate::search::{self, strings::*};
 
 #[derive(Clone)]
@@ -19,69 +21,215 @@ pub struct Episode {
     pub episode: u32,
     pub is_movie: bool,
     pub year: Option<u32>,
+    /// Genres of the matching show or movie, only filled when searching a database.
+    pub genres: Vec<String>,
+    /// Provider and accuracy of the database result, only filled when searching a database.
+    pub search_match: Option<SearchMatch>,
+    pub release: ReleaseInfo,
+    /// Streams found by ffprobe, `None` when the file was not probed.
+    pub media_info: Option<MediaInfo>,
+    /// Release details of the file name that ffprobe contradicted.
+    pub probe_conflicts: Vec<String>,
+    /// Why the file could not be probed, it is then classified from its name only.
+    pub probe_error: Option<String>,
+    /// Sample, trailer or other extra of the title, `None` for the title itself.
+    pub extra: Option<ExtraKind>,
+    /// A movie sorted in a folder of its own, next to its extras.
+    pub own_folder: bool,
+    /// A special of a series, in season 0.
+    pub special: bool,
+    /// Title of the episode, only filled for specials found in a database.
+    pub title: Option<String>,
 }
 
 impl Episode {
-    pub fn new(full_path: &PathBuf) -> Self {
-        let filename = full_path.file_name().unwrap().to_str().unwrap();
-        let filename_clean = clean_filename(filename).unwrap_or_default();
+    /// Parse a media found in the `input` folder, whose name says nothing about the media.
+    pub fn new(full_path: &Path, input: Option<&Path>) -> Result<Self, ParseError> {
+        let (filename, extension) = split_file_name(full_path)?;
+        let filename_clean = clean_filename(&filename).unwrap_or_default();
 
         let mut ep = Episode {
-            full_path: full_path.clone(),
-            filename: filename.to_string(),
-            filename_clean: filename_clean.clone(),
-            extension: "unknown".to_string(),
-
-            name: "unknown".to_string(),
-            season: 0,
-            episode: 0,
-            is_movie: false,
-            year: None,
+            filename: filename.clone(),
+            filename_clean,
+            extension,
+            release: ReleaseInfo::parse(&filename),
+            ..Episode::blank(full_path)
         };
 
-        ep.fetch_infos();
+        ep.fetch_infos(input)?;
 
-        ep
+        Ok(ep)
     }
 
     #[cfg(test)]
-    pub fn new_test(filename: &str, is_movie: bool) -> Self {
-        let filename_clean = clean_filename(filename).unwrap_or_default();
+    pub fn new_test(path: &str, is_movie: bool) -> Self {
+        let (filename, _) = split_file_name(Path::new(path)).unwrap();
+        let filename_clean = clean_filename(&filename).unwrap_or_default();
 
         let mut ep = Episode {
-            full_path: filename.into(),
-            filename: filename.to_string(),
-            filename_clean: filename_clean.clone(),
+            release: ReleaseInfo::parse(&filename),
+            filename,
+            filename_clean,
             extension: "unknown".to_string(),
-
-            name: "unknown".to_string(),
-            season: 0,
-            episode: 0,
-            is_movie: false,
-            year: None,
+            ..Episode::blank(Path::new(path))
         };
 
-        ep.name = extract_series_name(&ep.filename_clean).unwrap();
-        ep.season = ep.extract_season();
-        ep.episode = ep.extract_episode();
-        ep.extension = ep.extract_extension();
+        ep.parse_name_and_numbers().unwrap();
+        ep.detect_extra(None);
+        ep.detect_special();
+        ep.extension = split_file_name(&ep.full_path).unwrap().1;
         ep.is_movie = is_movie;
         ep.year = ep.extract_year();
 
         ep
     }
 
+    /// An episode of `full_path` with nothing parsed yet.
+    pub(crate) fn blank(full_path: &Path) -> Self {
+        Episode {
+            full_path: full_path.to_path_buf(),
+            filename: String::new(),
+            filename_clean: String::new(),
+            extension: String::new(),
+
+            name: "unknown".to_string(),
+            season: 0,
+            episode: 0,
+            is_movie: false,
+            year: None,
+            genres: Vec::new(),
+            search_match: None,
+            release: ReleaseInfo::default(),
+            media_info: None,
+            probe_conflicts: Vec::new(),
+            probe_error: None,
+            extra: None,
+            own_folder: false,
+            special: false,
+            title: None,
+        }
+    }
+
     pub fn set_name(&mut self, name: &str) {
         self.name = name.to_string();
     }
 
-    fn fetch_infos(&mut self) {
-        self.name = extract_series_name(&self.filename_clean).unwrap();
+    /// Name, season, episode and year from the path only, for files that cannot be probed
+    /// like subtitles.
+    pub fn parse_path(&mut self) -> Result<(), ParseError> {
+        self.parse_name_and_numbers()?;
+        self.detect_special();
+        self.is_movie = self.is_movie();
+        self.year = self.extract_year();
+        Ok(())
+    }
+
+    fn fetch_infos(&mut self, input: Option<&Path>) -> Result<(), ParseError> {
+        self.parse_name_and_numbers()?;
+        self.detect_extra(input);
+        self.detect_special();
+        match probe::probe(&self.full_path) {
+            Ok(media_info) => {
+                self.probe_conflicts = self.release.apply_media_info(&media_info);
+                self.media_info = Some(media_info);
+            }
+            Err(e) => self.probe_error = Some(format!("{:#}", e)),
+        }
+        self.is_movie = self.is_movie();
+        self.year = self.extract_year();
+
+        Ok(())
+    }
+
+    /// Name, season and episode of the file name, completed by its release folder.
+    fn parse_name_and_numbers(&mut self) -> Result<(), ParseError> {
+        let name = parse_name(&self.filename_clean, &self.filename);
         self.season = self.extract_season();
         self.episode = self.extract_episode();
-        self.extension = self.extract_extension();
-        self.is_movie = self.is_movie().unwrap();
-        self.year = self.extract_year().is_none().then(|| 0);
+        match ReleaseFolder::parse(&self.full_path) {
+            Some(folder) => self.merge_folder(name, folder),
+            None => {
+                self.name = name?;
+                Ok(())
+            }
+        }
+    }
+
+    /// Take the name and numbers of the release folder when the file name tells neither,
+    /// e.g. `Show.S02E03.1080p/abc123.mkv`, and the season of a season pack when the file
+    /// only tells the episode, e.g. `Show.S02.1080p/E03.mkv`.
+    fn merge_folder(
+        &mut self,
+        name: Result<String, ParseError>,
+        folder: ReleaseFolder,
+    ) -> Result<(), ParseError> {
+        let numbered = self.season > 0 || self.episode > 0 || self.extract_year().is_some();
+        self.name = match (name, folder.name) {
+            (Ok(_), Some(folder_name)) if !numbered => {
+                self.season = folder.season;
+                self.episode = folder.episode;
+                folder_name
+            }
+            (Ok(name), _) => name,
+            (Err(_), Some(folder_name)) => folder_name,
+            (Err(e), None) => return Err(e),
+        };
+        if self.season == 0 && self.episode > 0 {
+            self.season = folder.season;
+        }
+        Ok(())
+    }
+
+    /// Find whether the file is an extra, and name it after the title it belongs to.
+    fn detect_extra(&mut self, input: Option<&Path>) {
+        self.extra = extra::detect(&self.full_path, &self.name, input);
+        if self.extra.is_some() {
+            self.name = extra::strip_suffix(&self.name);
+        }
+    }
+
+    /// Find whether the file is a special: an `S00E05` episode, or a `SP01`, `OVA`, `OAD`
+    /// or `Special` without a regular episode number. The first word is part of the title.
+    fn detect_special(&mut self) {
+        let marker = SPECIAL
+            .captures(&self.filename_clean)
+            .filter(|captures| captures.get(0).unwrap().start() > 0);
+        if SEASON_ZERO.is_match(&self.filename_clean) {
+            self.special = true;
+        } else if let Some(captures) = marker.filter(|_| self.episode == 0) {
+            self.special = true;
+            self.season = 0;
+            self.episode = captures
+                .get(1)
+                .or(captures.get(2))
+                .and_then(|number| number.as_str().parse().ok())
+                .unwrap_or(0);
+        }
+
+        if self.special {
+            if let Some(marker) = SPECIAL.find(&self.name).filter(|marker| marker.start() > 0) {
+                self.name = self.name[..marker.start()].trim().to_string();
+            }
+        }
+    }
+
+    /// Name a special from the specials of its show in a database, in airing order: the
+    /// n-th one for `SP0n`, or the one whose title is in the file name.
+    pub fn name_special(&mut self, specials: &[String]) {
+        let filename = self.filename_clean.to_lowercase();
+        let index = match self.episode {
+            0 => specials
+                .iter()
+                .enumerate()
+                .filter(|(_, title)| filename.contains(&title.to_lowercase()))
+                .max_by_key(|(_, title)| title.len())
+                .map(|(index, _)| index),
+            number => Some(number as usize - 1).filter(|&index| index < specials.len()),
+        };
+        if let Some(index) = index {
+            self.episode = index as u32 + 1;
+            self.title = Some(specials[index].clone());
+        }
     }
 
     fn extract_season(&self) -> u32 {
@@ -101,7 +249,7 @@ impl Episode {
             // Check for patterns like "2nd Season", "3rd Season", etc.
             if let Some(digit) = season_parts[i].chars().next() {
                 // Check if the first character is a digit
-                if digit.is_digit(10) && season_parts[i].len() > 2 {
+                if digit.is_ascii_digit() && season_parts[i].len() > 2 {
                     // Ensure it ends with a valid suffix followed by "Season"
                     if (season_parts[i].ends_with("st")
                         || season_parts[i].ends_with("nd")
@@ -121,10 +269,11 @@ impl Episode {
             }
 
             // Check for "Season X" pattern
-            if season_parts[i].eq_ignore_ascii_case("season") && i + 1 < season_parts.len() {
-                if season_parts[i + 1].chars().all(char::is_numeric) {
-                    return season_parts[i + 1].parse::<u32>().unwrap_or(1);
-                }
+            if season_parts[i].eq_ignore_ascii_case("season")
+                && i + 1 < season_parts.len()
+                && season_parts[i + 1].chars().all(char::is_numeric)
+            {
+                return season_parts[i + 1].parse::<u32>().unwrap_or(1);
             }
         }
 
@@ -170,10 +319,11 @@ impl Episode {
             }
 
             // Check for "Episode X" pattern
-            if episode[i].eq_ignore_ascii_case("episode") && i + 1 < episode.len() {
-                if episode[i + 1].chars().all(char::is_numeric) {
-                    return episode[i + 1].parse::<u32>().unwrap_or(1);
-                }
+            if episode[i].eq_ignore_ascii_case("episode")
+                && i + 1 < episode.len()
+                && episode[i + 1].chars().all(char::is_numeric)
+            {
+                return episode[i + 1].parse::<u32>().unwrap_or(1);
             }
 
             // Check for standalone number after season identifier (S2 01)
@@ -208,63 +358,41 @@ impl Episode {
         0
     }
 
-    fn extract_extension(&self) -> String {
-        let extension = self
-            .full_path
-            .extension()
-            .unwrap()
-            .to_str()
-            .unwrap()
-            .to_string();
-
-        extension
-    }
+    fn is_movie(&self) -> bool {
+        if self.special {
+            return false;
+        }
 
-    fn is_movie(&self) -> Result<bool> {
         // Check if the filename explicitly indicates a movie
         if self.filename.contains("Film") || self.filename.contains("Movie") {
-            return Ok(true);
+            return true;
         }
 
         // Check for the absence of season and episode (which could indicate a movie)
         if self.season == 0 && self.episode == 0 {
-            return Ok(true);
+            return true;
         }
 
         // If it's part of a season but has no episode info, treat it with suspicion
         if self.season > 0 && self.episode == 0 {
-            return Ok(true);
+            return true;
         }
 
         // Regex for season-episode patterns like "S01E01"
         let series_pattern = Regex::new(r"S\d{1,2}E\d{1,2}").unwrap();
 
-        // Try to determine based on file duration
-        match ffprobe(&self.full_path) {
-            Ok(metadata) => {
-                if let Some(duration_str) = metadata.format.duration {
-                    let duration = duration_str.parse::<f32>().unwrap_or(0.0);
-
-                    // If duration exceeds 4200 seconds, check further
-                    if duration > 4200.0 {
-                        // Check if the filename has a series-like pattern
-                        if series_pattern.is_match(&self.filename) {
-                            // If it matches a season-episode pattern, it's likely a series
-                            return Ok(false);
-                        } else {
-                            // If no series pattern is found and duration is long, treat it as a movie
-                            return Ok(true);
-                        }
-                    }
-                }
-            }
-            Err(e) => {
-                bail!("Error while parsing file with ffprobe: {:?}", e);
+        // Try to determine based on file duration, when the file could be probed
+        if let Some(duration) = self.media_info.as_ref().and_then(|info| info.duration) {
+            // If duration exceeds 4200 seconds, check further
+            if duration > 4200.0 {
+                // If it matches a season-episode pattern, it's likely a series,
+                // otherwise the duration is long enough for a movie
+                return !series_pattern.is_match(&self.filename);
             }
         }
 
         // Default case: if it didn't match other criteria, assume it's a series
-        Ok(false)
+        false
     }
 
     fn extract_year(&self) -> Option<u32> {
@@ -274,3 +402,187 @@ impl Episode {
             .filter(|&year| year > 0)
     }
 }
+
+/// File name and extension of a media or subtitle path.
+pub fn split_file_name(path: &Path) -> Result<(String, String), ParseError> {
+    let filename = path
+        .file_name()
+        .ok_or_else(|| ParseError::NoFileName(path.to_path_buf()))?
+        .to_str()
+        .ok_or_else(|| ParseError::NotUtf8(path.to_path_buf()))?;
+    let extension = path
+        .extension()
+        .ok_or_else(|| ParseError::NoExtension(path.to_path_buf()))?
+        .to_str()
+        .ok_or_else(|| ParseError::NotUtf8(path.to_path_buf()))?;
+
+    Ok((filename.to_string(), extension.to_string()))
+}
+
+/// What the parent and grandparent folders of a file tell about it.
+struct ReleaseFolder {
+    name: Option<String>,
+    season: u32,
+    episode: u32,
+}
+
+impl ReleaseFolder {
+    /// The closest folder naming a release, with a season, an episode or a year, e.g.
+    /// `Show.S02.1080p/` or `Movie.2010/`, or naming the show of a `Season 2/` folder.
+    /// `None` when neither folder tells anything.
+    fn parse(path: &Path) -> Option<Self> {
+        let mut season = 0;
+        for dir in path.ancestors().skip(1).take(2) {
+            let Some(dir_name) = dir.file_name().and_then(|name| name.to_str()) else {
+                break;
+            };
+            // `clean_filename` drops the extension, a folder name has none
+            let filename_clean = clean_filename(&format!("{}.dir", dir_name)).unwrap_or_default();
+            let folder = Episode {
+                filename: dir_name.to_string(),
+                filename_clean,
+                ..Episode::blank(dir)
+            };
+            let folder_season = folder.extract_season();
+            let numbered = folder_season > 0 || folder.extract_year().is_some();
+            if let Ok(name) = parse_name(&folder.filename_clean, dir_name) {
+                if numbered || season > 0 {
+                    return Some(Self {
+                        name: Some(name),
+                        season: if season > 0 { season } else { folder_season },
+                        episode: folder.extract_episode(),
+                    });
+                }
+            }
+            if season == 0 {
+                season = folder_season;
+            }
+        }
+
+        (season > 0).then_some(Self {
+            name: None,
+            season,
+            episode: 0,
+        })
+    }
+}
+
+/// Season of the folder holding a sorted file, looking past a `Subtitles` folder. The
+/// `Specials` folder is season 0.
+pub fn folder_season(path: &Path) -> Option<u32> {
+    let mut dir = path.parent()?;
+    if dir.file_name()? == "Subtitles" {
+        dir = dir.parent()?;
+    }
+    let dir_name = dir.file_name()?.to_str()?;
+    if dir_name.eq_ignore_ascii_case("Specials") {
+        return Some(0);
+    }
+    let captures = SEASON_DIR.captures(dir_name)?;
+    captures[1].parse().ok()
+}
+
+/// Show or movie name of a cleaned file name, which must not be empty.
+pub fn parse_name(filename_clean: &str, filename: &str) -> Result<String, ParseError> {
+    extract_series_name(filename_clean)
+        .ok()
+        .filter(|name| !name.is_empty())
+        .ok_or_else(|| ParseError::NoName(filename.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cmd::sort::dry_run_tree;
+    use crate::subtitle::{Subtitle, SubtitleLayout};
+
+    #[test]
+    fn test_folder_season() {
+        let series = PathBuf::from("Series/Show");
+        assert_eq!(folder_season(&series.join("S02/Show - E01.mkv")), Some(2));
+        assert_eq!(
+            folder_season(&series.join("Season 3/Subtitles/Show - E01.en.srt")),
+            Some(3)
+        );
+        assert_eq!(folder_season(Path::new("Downloads/Show - E01.mkv")), None);
+    }
+
+    #[test]
+    fn test_specials() {
+        let special = |name: &str| {
+            let episode = Episode::new_test(name, false);
+            (
+                episode.special,
+                episode.season,
+                episode.episode,
+                episode.name,
+            )
+        };
+        assert_eq!(
+            special("Show.S00E05.1080p.mkv"),
+            (true, 0, 5, "Show".into())
+        );
+        assert_eq!(special("Show.SP01.mkv"), (true, 0, 1, "Show".into()));
+        assert_eq!(special("Show.S01.OVA.2.mkv"), (true, 0, 2, "Show".into()));
+        assert_eq!(special("Show.OAD.mkv"), (true, 0, 0, "Show".into()));
+        assert!(!special("Show.S02E03.Christmas.Special.mkv").0);
+        assert!(!special("Special.Ops.S01E01.mkv").0);
+        assert_eq!(
+            folder_season(Path::new("Series/Show/Specials/Show - E01.mkv")),
+            Some(0)
+        );
+
+        let mut named = Episode::new_test("Show.OVA.Christmas.Special.mkv", false);
+        named.name_special(&["Pilot".to_string(), "Christmas Special".to_string()]);
+        assert_eq!(
+            (named.episode, named.title.as_deref()),
+            (2, Some("Christmas Special"))
+        );
+        let mut numbered = Episode::new_test("Show.SP03.mkv", false);
+        numbered.name_special(&["Pilot".to_string()]);
+        assert_eq!((numbered.episode, numbered.title), (3, None));
+
+        let episodes = [
+            Episode::new_test("Show.S00E05.1080p.mkv", false),
+            Episode::new_test("Show.OAD.mkv", false),
+            named,
+        ];
+        let lines = dry_run_tree(&episodes, &[], &[], SubtitleLayout::Folder, |_| {
+            (PathBuf::from("Series"), "Series".to_string())
+        });
+        assert_eq!(
+            lines[1..6],
+            [
+                "└─ Show/",
+                "   └─ Specials/",
+                "      ├─ Show - E02 - Christmas Special.mkv",
+                "      ├─ Show - E05.mkv",
+                "      └─ Show.OAD.mkv",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_release_folders() {
+        let parsed = |path: &str| {
+            let episode = Episode::new_test(path, false);
+            (episode.name, episode.season, episode.episode)
+        };
+        assert_eq!(
+            parsed("Show.Name.S02E03.1080p.WEB/abc123.mkv"),
+            ("Show Name".to_string(), 2, 3)
+        );
+        assert_eq!(parsed("Show.S02.1080p/E03.mkv"), ("Show".to_string(), 2, 3));
+        assert_eq!(parsed("Show/Season 2/E05.mkv"), ("Show".to_string(), 2, 5));
+        assert_eq!(
+            parsed("Show.S02.1080p/Other.S01E04.mkv"),
+            ("Other".to_string(), 1, 4)
+        );
+        assert_eq!(parsed("Movie.2010.1080p/abc123.mkv").0, "Movie");
+        assert_eq!(parsed("Downloads/Inception.mkv").0, "Inception");
+
+        let subtitle = Subtitle::new("Show.Name.S02E03.1080p.WEB/Subs/English.srt".into()).unwrap();
+        assert_eq!(subtitle.episode.name, "Show Name");
+        assert_eq!((subtitle.episode.season, subtitle.episode.episode), (2, 3));
+    }
+}
@@ -1,7 +1,9 @@
 use std::fmt::{self, Display, Formatter};
+use std::path::PathBuf;
 
 
 /// Custom error type for early exit.
+#[allow(dead_code)]
 #[derive(Debug)]
 pub struct SilentExit {
     #[allow(dead_code)]
@@ -13,3 +15,27 @@ impl Display for SilentExit {
         Ok(())
     }
 }
+
+/// Why a file name could not be turned into an episode or a subtitle.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseError {
+    NoFileName(PathBuf),
+    NotUtf8(PathBuf),
+    NoExtension(PathBuf),
+    NoName(String),
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::NoFileName(path) => write!(f, "{:?} has no file name", path),
+            ParseError::NotUtf8(path) => write!(f, "{:?} is not a valid UTF-8 name", path),
+            ParseError::NoExtension(path) => write!(f, "{:?} has no extension", path),
+            ParseError::NoName(filename) => {
+                write!(f, "could not find a show or movie name in {:?}", filename)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
@@ -0,0 +1,202 @@
+use std::fs;
+use std::path::Path;
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::episode::Episode;
+use crate::search::strings::{EXTRA_SUFFIX, EXTRA_WORDS};
+
+/// A video that is not the episode or movie itself.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExtraKind {
+    /// A short cut of a release, never sorted.
+    Sample,
+    Trailer,
+    Featurette,
+    BehindTheScenes,
+    DeletedScene,
+    Interview,
+    Other,
+}
+
+impl ExtraKind {
+    /// Folder of the extras of a title, as Plex and Jellyfin expect.
+    pub fn folder(self) -> Option<&'static str> {
+        match self {
+            ExtraKind::Sample => None,
+            ExtraKind::Trailer => Some("Trailers"),
+            ExtraKind::Featurette => Some("Featurettes"),
+            ExtraKind::BehindTheScenes => Some("Behind The Scenes"),
+            ExtraKind::DeletedScene => Some("Deleted Scenes"),
+            ExtraKind::Interview => Some("Interviews"),
+            ExtraKind::Other => Some("Extras"),
+        }
+    }
+
+    /// Kind of a word of a file name, or of a folder name, without separators.
+    fn from_word(word: &str) -> Option<Self> {
+        let word: String = word
+            .chars()
+            .filter(|c| c.is_alphanumeric())
+            .collect::<String>()
+            .to_lowercase();
+        match word.as_str() {
+            "sample" | "samples" => Some(ExtraKind::Sample),
+            "trailer" | "trailers" | "teaser" => Some(ExtraKind::Trailer),
+            "featurette" | "featurettes" => Some(ExtraKind::Featurette),
+            "behindthescenes" | "makingof" => Some(ExtraKind::BehindTheScenes),
+            "deleted" | "deletedscene" | "deletedscenes" => Some(ExtraKind::DeletedScene),
+            "interview" | "interviews" => Some(ExtraKind::Interview),
+            "extra" | "extras" | "other" | "bonus" => Some(ExtraKind::Other),
+            _ => None,
+        }
+    }
+}
+
+/// What to do with the samples found in the inputs.
+#[derive(Serialize, Deserialize, ValueEnum, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SamplePolicy {
+    /// Leave them in the input.
+    #[default]
+    Skip,
+    Delete,
+}
+
+/// Kind of extra of a video, from its folder, e.g. `Featurettes/`, from a Plex suffix,
+/// e.g. `Movie-trailer.mkv`, or from a word after the title, e.g. `Movie.2010.Sample.mkv`.
+/// Words of the title itself are not looked at, `The Interview` is a movie, and neither
+/// is the `input` folder the video was found in.
+pub fn detect(path: &Path, name: &str, input: Option<&Path>) -> Option<ExtraKind> {
+    let folder = path
+        .parent()
+        .filter(|&dir| Some(dir) != input)
+        .and_then(|dir| dir.file_name())
+        .and_then(|dir| dir.to_str());
+    if let Some(kind) = folder.and_then(ExtraKind::from_word) {
+        return Some(kind);
+    }
+
+    let stem = path.file_stem()?.to_str()?;
+    if let Some(captures) = EXTRA_SUFFIX.captures(stem) {
+        return ExtraKind::from_word(&captures[1]);
+    }
+
+    let words = normalize(stem);
+    let title = normalize(name);
+    let rest = match words.strip_prefix(&title) {
+        Some(rest) if !title.is_empty() => rest,
+        _ => words
+            .split_once(' ')
+            .map(|(_, rest)| rest)
+            .unwrap_or_default(),
+    };
+    let captures = EXTRA_WORDS.captures(rest)?;
+    ExtraKind::from_word(&captures[1])
+}
+
+/// Title without the extra words it was parsed with, `Movie trailer` gives `Movie`.
+pub fn strip_suffix(name: &str) -> String {
+    let mut words: Vec<&str> = name.split_whitespace().collect();
+    for length in [3, 2, 1] {
+        let start = words.len().saturating_sub(length);
+        if start > 0 && ExtraKind::from_word(&words[start..].concat()).is_some() {
+            words.truncate(start);
+            break;
+        }
+    }
+    words.join(" ")
+}
+
+/// Whether a media is a sample: named so, or shorter than `max_duration` seconds when
+/// its duration is known, smaller than `max_size` MB otherwise. A zero threshold is off.
+pub fn is_sample(episode: &Episode, max_size: u64, max_duration: u64) -> bool {
+    if episode.extra == Some(ExtraKind::Sample) {
+        return true;
+    }
+    if episode.extra.is_some() {
+        return false;
+    }
+
+    match episode.media_info.as_ref().and_then(|info| info.duration) {
+        Some(duration) => max_duration > 0 && duration < max_duration as f64,
+        None => {
+            max_size > 0
+                && fs::metadata(&episode.full_path)
+                    .is_ok_and(|metadata| metadata.len() < max_size * 1_000_000)
+        }
+    }
+}
+
+/// Lowercase words of a name, separated by single spaces.
+fn normalize(name: &str) -> String {
+    name.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<&str>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    use crate::cmd::sort::dry_run_tree;
+    use crate::probe::MediaInfo;
+    use crate::subtitle::SubtitleLayout;
+
+    #[test]
+    fn test_extras() {
+        let extra = |name: &str| Episode::new_test(name, true).extra;
+        assert_eq!(
+            extra("Movie.2010.Trailer.1080p.mkv"),
+            Some(ExtraKind::Trailer)
+        );
+        assert_eq!(extra("Show.S01E01.Sample.mkv"), Some(ExtraKind::Sample));
+        assert_eq!(extra("Sample/show.s01e01.mkv"), Some(ExtraKind::Sample));
+        assert_eq!(
+            extra("Featurettes/Making the Show.mkv"),
+            Some(ExtraKind::Featurette)
+        );
+        assert_eq!(
+            extra("Movie.2010.Making.Of.mkv"),
+            Some(ExtraKind::BehindTheScenes)
+        );
+        assert_eq!(extra("The.Interview.2014.1080p.mkv"), None);
+        assert_eq!(extra("Trailer.Park.Boys.S01E01.mkv"), None);
+        let input = Path::new("Downloads/Other");
+        let path = input.join("Movie.2010.mkv");
+        assert_eq!(detect(&path, "Movie", Some(input)), None);
+        assert_eq!(detect(&path, "Movie", None), Some(ExtraKind::Other));
+
+        let inline = Episode::new_test("Inception-trailer.mkv", true);
+        assert_eq!(inline.extra, Some(ExtraKind::Trailer));
+        assert_eq!(inline.name, "Inception");
+
+        let mut short = Episode::new_test("Show.S01E02.mkv", false);
+        short.media_info = Some(MediaInfo {
+            duration: Some(60.0),
+            ..Default::default()
+        });
+        assert!(is_sample(&short, 50, 180));
+        assert!(!is_sample(&short, 50, 0));
+        assert!(!is_sample(&short, 0, 0));
+        short.media_info.as_mut().unwrap().duration = Some(1500.0);
+        assert!(!is_sample(&short, 50, 180));
+
+        let trailer = Episode::new_test("Movie.2010.Trailer.1080p.mkv", true);
+        let lines = dry_run_tree(&[trailer], &[], &[], SubtitleLayout::Folder, |_| {
+            (PathBuf::from("Films"), "Films".to_string())
+        });
+        assert_eq!(
+            lines[1..4],
+            [
+                "└─ Movie (2010)/",
+                "   └─ Trailers/",
+                "      └─ Movie.2010.Trailer.1080p.mkv",
+            ]
+        );
+    }
+}
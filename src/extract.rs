@@ -0,0 +1,53 @@
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use anyhow::{bail, Context, Result};
+use once_cell::sync::Lazy;
+
+/// Whether an `ffmpeg` binary can be run, checked once per run.
+static AVAILABLE: Lazy<bool> = Lazy::new(|| {
+    Command::new("ffmpeg")
+        .arg("-version")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map(|status| status.success())
+        .unwrap_or(false)
+});
+
+pub fn is_available() -> bool {
+    *AVAILABLE
+}
+
+/// Extension of the sidecar for a subtitle codec, `None` for picture subtitles like PGS
+/// or VobSub that cannot be written as text.
+pub fn text_extension(codec: &str) -> Option<&'static str> {
+    match codec {
+        "subrip" | "srt" | "mov_text" | "text" => Some("srt"),
+        "ass" | "ssa" => Some("ass"),
+        "webvtt" => Some("vtt"),
+        _ => None,
+    }
+}
+
+/// Write the subtitle stream `index` of a media to `to`. ffmpeg converts it to the format
+/// of the extension of `to` when needed, and never overwrites an existing file.
+pub fn extract_subtitle(media: &Path, index: i64, to: &Path) -> Result<()> {
+    let output = Command::new("ffmpeg")
+        .args(["-v", "error", "-n", "-i"])
+        .arg(media)
+        .args(["-map", &format!("0:{}", index)])
+        .arg(to)
+        .stdin(Stdio::null())
+        .output()
+        .context("Could not run ffmpeg")?;
+
+    if !output.status.success() {
+        bail!(
+            "ffmpeg could not extract stream {}: {}",
+            index,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
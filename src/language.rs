@@ -0,0 +1,280 @@
+use std::fs;
+use std::path::Path;
+
+use isolang::Language;
+
+use crate::search::strings::SUBTITLE_TAGS;
+
+/// Language of a subtitle file, and what kind of track it is.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SubtitleLanguage {
+    /// ISO 639-1 code, or ISO 639-3 for languages without one.
+    pub code: Option<String>,
+    /// Only the foreign dialogues and on-screen text.
+    pub forced: bool,
+    /// Subtitles for the deaf and hard of hearing.
+    pub sdh: bool,
+    /// Closed captions.
+    pub cc: bool,
+}
+
+impl SubtitleLanguage {
+    /// Keep the code already found, add the flags of `other`.
+    fn merge(&mut self, other: SubtitleLanguage) {
+        self.code = self.code.take().or(other.code);
+        self.forced |= other.forced;
+        self.sdh |= other.sdh;
+        self.cc |= other.cc;
+    }
+
+    /// Set the flag named by a tag, if it is one.
+    fn set_flag(&mut self, tag: &str) -> bool {
+        match tag {
+            "forced" | "foreign" => self.forced = true,
+            "sdh" => self.sdh = true,
+            "cc" => self.cc = true,
+            _ => return false,
+        }
+        true
+    }
+}
+
+/// Release tags of French subtitles.
+const FRENCH_TAGS: [&str; 8] = [
+    "vostfr",
+    "vost",
+    "vf",
+    "vff",
+    "vfq",
+    "vfi",
+    "truefrench",
+    "subfrench",
+];
+
+pub fn is_flag(tag: &str) -> bool {
+    matches!(tag, "forced" | "foreign" | "sdh" | "cc")
+}
+
+/// Find the language of a subtitle from, in order, the tags of its file name, the
+/// `[Script Info]` of an ASS script, then the text of its dialogues.
+pub fn detect(path: &Path) -> SubtitleLanguage {
+    let mut language = from_filename(path);
+    if language.code.is_some() {
+        return language;
+    }
+
+    let Ok(bytes) = fs::read(path) else {
+        return language;
+    };
+    let content = String::from_utf8_lossy(&bytes);
+    language.merge(from_script_info(&content));
+    if language.code.is_none() {
+        language.code = from_text(&content);
+    }
+
+    language
+}
+
+/// Language and flags from the tags at the end of the name, e.g. `Show.S01E01.en.forced`,
+/// or from a release tag or language name anywhere in it, e.g. `VOSTFR` or `English`.
+pub fn from_filename(path: &Path) -> SubtitleLanguage {
+    let mut language = SubtitleLanguage::default();
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    // The first token is the title, a movie named `It` is not in Italian
+    let tokens: Vec<&str> = stem
+        .split(|c: char| !c.is_alphanumeric() && c != '-')
+        .filter(|token| !token.is_empty() && *token != "-")
+        .skip(1)
+        .collect();
+
+    let trailing: Vec<&str> = tokens
+        .iter()
+        .rev()
+        .take_while(|token| is_flag(token) || normalize(token).is_some())
+        .copied()
+        .collect();
+    let codes = trailing
+        .iter()
+        .filter(|token| normalize(token).is_some())
+        .count();
+    for token in &trailing {
+        // `hi` is the hearing impaired tag next to another language, Hindi on its own
+        if *token == "hi" && codes > 1 {
+            language.sdh = true;
+        } else if !language.set_flag(token) && language.code.is_none() {
+            language.code = normalize(token);
+        }
+    }
+
+    for token in &tokens {
+        if language.code.is_none() && (token.len() > 3 || FRENCH_TAGS.contains(token)) {
+            language.code = normalize(token);
+        }
+        if *token != "cc" {
+            language.set_flag(token);
+        }
+    }
+
+    language
+}
+
+/// Language and flags from the `Language` or `Title` field of an ASS `[Script Info]`.
+pub fn from_script_info(content: &str) -> SubtitleLanguage {
+    let mut language = SubtitleLanguage::default();
+    let fields = content
+        .lines()
+        .map(str::trim)
+        .skip_while(|line| !line.eq_ignore_ascii_case("[Script Info]"))
+        .skip(1)
+        .take_while(|line| !line.starts_with('['));
+
+    for line in fields {
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        if !key.eq_ignore_ascii_case("Language") && !key.eq_ignore_ascii_case("Title") {
+            continue;
+        }
+
+        let value = value.trim().to_lowercase();
+        let mut field = SubtitleLanguage {
+            code: normalize(&value),
+            ..Default::default()
+        };
+        for token in value.split(|c: char| !c.is_alphanumeric()) {
+            if !field.set_flag(token) && field.code.is_none() && token.len() > 3 {
+                field.code = normalize(token);
+            }
+        }
+        language.merge(field);
+    }
+
+    language
+}
+
+/// Statistical detection on the dialogues, when they are long enough to be reliable.
+pub fn from_text(content: &str) -> Option<String> {
+    let info = whatlang::detect(&dialogue_text(content))?;
+    if !info.is_reliable() {
+        return None;
+    }
+    Language::from_639_3(info.lang().code()).map(iso_code)
+}
+
+/// Text of the dialogues of an SRT, VTT or ASS file, without timings and formatting.
+fn dialogue_text(content: &str) -> String {
+    let is_ass = content.contains("[Events]");
+    let mut text = String::new();
+
+    for line in content.lines().map(str::trim) {
+        let line = match line.strip_prefix("Dialogue:") {
+            Some(dialogue) => dialogue.splitn(10, ',').nth(9).unwrap_or_default(),
+            None if is_ass => continue,
+            None => line,
+        };
+        if line.is_empty()
+            || line == "WEBVTT"
+            || line.contains("-->")
+            || line.chars().all(|c| c.is_ascii_digit())
+        {
+            continue;
+        }
+
+        text.push_str(&SUBTITLE_TAGS.replace_all(line, " "));
+        text.push('\n');
+        if text.len() > 20_000 {
+            break;
+        }
+    }
+
+    text
+}
+
+/// ISO code of a language code, name or release tag, e.g. `eng`, `English` or `VOSTFR`
+/// give `en`. Region suffixes like `pt-BR` are dropped.
+pub fn normalize(tag: &str) -> Option<String> {
+    let tag = tag.trim().to_lowercase();
+    let tag = tag.split(['-', '_']).next()?;
+
+    let language = match tag {
+        tag if FRENCH_TAGS.contains(&tag) => Some(Language::Fra),
+        // ISO 639-2/B codes, still common in file names
+        "fre" => Some(Language::Fra),
+        "ger" => Some(Language::Deu),
+        "dut" => Some(Language::Nld),
+        "chi" => Some(Language::Zho),
+        "cze" => Some(Language::Ces),
+        "gre" => Some(Language::Ell),
+        "rum" => Some(Language::Ron),
+        "slo" => Some(Language::Slk),
+        "per" => Some(Language::Fas),
+        "ice" => Some(Language::Isl),
+        "may" => Some(Language::Msa),
+        // Only languages with a two letter code, most three letter words are a language
+        _ => Language::from_639_1(tag)
+            .or_else(|| Language::from_639_3(tag))
+            .or_else(|| Language::from_name_lowercase(tag))
+            .filter(|language| language.to_639_1().is_some()),
+    };
+
+    language.map(iso_code)
+}
+
+fn iso_code(language: Language) -> String {
+    language
+        .to_639_1()
+        .unwrap_or(language.to_639_3())
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_subtitle_language() {
+        let detect = |name: &str| from_filename(Path::new(name));
+        let code = |name: &str| detect(name).code;
+
+        assert_eq!(code("Breaking.Bad.S01E01.en.srt").as_deref(), Some("en"));
+        assert_eq!(code("Breaking.Bad.S01E01.fre.srt").as_deref(), Some("fr"));
+        assert_eq!(code("Show.S01E01.pt-BR.srt").as_deref(), Some("pt"));
+        assert_eq!(code("Show.S01E01.VOSTFR.1080p.srt").as_deref(), Some("fr"));
+        assert_eq!(code("Show S01E01 English.srt").as_deref(), Some("en"));
+        assert_eq!(code("Show.S01E01.hi.srt").as_deref(), Some("hi"));
+        assert_eq!(code("It.srt"), None);
+        assert_eq!(code("Breaking.Bad.S01E01.srt"), None);
+        assert_eq!(
+            detect("Movie.2010.eng.forced.srt"),
+            SubtitleLanguage {
+                code: Some("en".to_string()),
+                forced: true,
+                ..Default::default()
+            }
+        );
+        let hearing_impaired = detect("Movie.2010.en.hi.srt");
+        assert_eq!(hearing_impaired.code.as_deref(), Some("en"));
+        assert!(hearing_impaired.sdh);
+        assert!(detect("Movie.2010.es.cc.srt").cc);
+
+        let script = "[Script Info]\nTitle: German (Forced)\nScriptType: v4.00+\n\n[Events]\n";
+        let script = from_script_info(script);
+        assert_eq!(script.code.as_deref(), Some("de"));
+        assert!(script.forced);
+        assert_eq!(
+            from_script_info("[Script Info]\nLanguage: ja\n")
+                .code
+                .as_deref(),
+            Some("ja")
+        );
+
+        let srt = "1\n00:00:01,000 --> 00:00:03,000\n<i>Je ne sais pas où il est parti ce matin.</i>\n\n\
+                   2\n00:00:04,000 --> 00:00:06,000\nIl faut absolument le retrouver avant la nuit.\n\n\
+                   3\n00:00:07,000 --> 00:00:09,000\nTu as raison, allons chercher les autres.\n";
+        assert_eq!(from_text(srt).as_deref(), Some("fr"));
+        assert_eq!(from_text("1\n00:00:01,000 --> 00:00:02,000\n...\n"), None);
+    }
+}
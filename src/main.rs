@@ -1,13 +1,29 @@
+mod archive;
 mod cmd;
+mod config;
+mod convert;
+mod duplicate;
 mod episode;
 mod error;
+mod extra;
+mod extract;
+mod language;
+mod plan;
+mod probe;
+mod quality;
+mod release;
+mod report;
 mod search;
+mod sidecar;
 mod subtitle;
 
 mod tui;
 
+use crate::cmd::{Cmd, Run};
 use crate::tui::app::*;
 use crate::tui::*;
+use clap::Parser;
+use color_eyre::eyre::eyre;
 use color_eyre::Result;
 
 //-- main code --
@@ -15,6 +31,10 @@ use color_eyre::Result;
 
 fn main() -> Result<()> {
     color_eyre::install()?;
+    // A subcommand runs the command line, no argument opens the interface
+    if std::env::args_os().len() > 1 {
+        return Cmd::parse().run().map_err(|e| eyre!("{:#}", e));
+    }
     let mut terminal = ui::init()?;
     let app_result = App::default().run(&mut terminal);
     if let Err(err) = ui::restore() {
@@ -29,9 +49,18 @@ fn main() -> Result<()> {
 #[cfg(test)]
 mod tests {
     use crate::cmd::sort::dry_run_sort;
-    use crate::subtitle::Subtitle;
+    use crate::config::profile::{ProfileConfig, ProfileFlags, PROFILE_VERSION};
+    use crate::config::resolve::{resolve_from, Source};
+    use crate::config::route::{Pattern, Route};
+    use crate::error::ParseError;
+    use crate::probe::{FileKey, MediaInfo, ProbeCache};
+    use crate::release::ReleaseInfo;
+    use crate::report::{write_report, Action, FileType, ReportRow};
+    use crate::search::result::MediaType;
+    use crate::subtitle::{Subtitle, SubtitleLayout};
     use episode::Episode;
     use std::path::PathBuf;
+    use std::time::Duration;
 
     use super::*;
 
@@ -112,8 +141,12 @@ mod tests {
         dry_run_sort(
             &episodes,
             &subtitles,
-            "Series".to_string(),
-            "Movies".to_string(),
+            &[],
+            SubtitleLayout::Folder,
+            |episode| {
+                let library = if episode.is_movie { "Movies" } else { "Series" };
+                (PathBuf::from(library), library.to_string())
+            },
         )
         .unwrap();
 
@@ -174,7 +207,7 @@ mod tests {
     }
 
     fn create_test_subtitle(filename: &str) -> Subtitle {
-        Subtitle::new(PathBuf::from(filename))
+        Subtitle::new(PathBuf::from(filename)).unwrap()
     }
 
     #[test]
@@ -227,4 +260,411 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn test_profile_flags_validation() {
+        let mut flags = ProfileFlags::default();
+
+        flags.set_from_str("recursive=true").unwrap();
+        flags.set_from_str("threads=4").unwrap();
+        flags.set_from_str("tv-template=Shows").unwrap();
+        flags.set_from_str("movie-template=2024").unwrap();
+        flags.set_from_str("webhook=true").unwrap();
+        assert_eq!(flags.recursive, Some(true));
+        assert_eq!(flags.threads, Some(4));
+        assert_eq!(flags.tv_template.as_deref(), Some("Shows"));
+        assert_eq!(flags.movie_template.as_deref(), Some("2024"));
+        assert_eq!(flags.webhook.as_deref(), Some("true"));
+        assert_eq!(flags.verbose, None);
+
+        assert!(flags.set_from_str("recursve=true").is_err());
+        assert!(flags.set_from_str("verbose=yes").is_err());
+        assert!(flags.set_from_str("threads").is_err());
+    }
+
+    #[test]
+    fn test_profile_migration() {
+        let profile = serde_json::json!({
+            "name": "Angel",
+            "input": "/downloads",
+            "output": "/medias",
+            "color": "blue",
+            "flags": { "verbose": true, "recursve": true },
+        });
+
+        let profile = ProfileConfig::from_value(profile).unwrap();
+        assert_eq!(profile.version, PROFILE_VERSION);
+        assert_eq!(profile.inputs, vec![PathBuf::from("/downloads")]);
+        assert_eq!(profile.flags.verbose, Some(true));
+        assert_eq!(profile.flags.recursive, None);
+
+        let profile = serde_json::json!({
+            "version": PROFILE_VERSION,
+            "name": "Angel",
+            "inputs": ["/downloads"],
+            "output": "/medias",
+            "flags": { "recursve": true },
+        });
+        assert!(ProfileConfig::from_value(profile).is_err());
+    }
+
+    #[test]
+    fn test_settings_precedence() {
+        let dir = std::env::temp_dir().join("mediasort_precedence");
+        std::fs::create_dir_all(&dir).unwrap();
+        let config_path = dir.join("config.toml");
+        std::fs::write(&config_path, "[flags]\nthreads = 3\nrecursive = false\n").unwrap();
+        let env = std::collections::HashMap::from([
+            ("MEDIASORT_DRY_RUN".to_string(), "true".to_string()),
+            ("MEDIASORT_TV_TEMPLATE".to_string(), "2024".to_string()),
+        ]);
+        let env_var = |var: &str| env.get(var).cloned();
+
+        let mut profile = ProfileConfig::new("Angel", vec!["/downloads".into()], "/medias".into());
+        profile.flags.set_from_str("recursive=true").unwrap();
+        profile.flags.set_from_str("movie-template=Movies").unwrap();
+
+        let mut cli = serde_json::Map::new();
+        cli.insert("output".into(), serde_json::json!("/other"));
+        cli.insert("dry-run".into(), serde_json::json!(false));
+
+        let resolved = resolve_from(config_path.clone(), env_var, Some(&profile), cli).unwrap();
+        assert_eq!(resolved.locations.inputs, vec![PathBuf::from("/downloads")]);
+        assert_eq!(resolved.locations.output, Some(PathBuf::from("/other")));
+        assert_eq!(resolved.flags.threads, 3);
+        assert!(resolved.flags.recursive);
+        assert!(!resolved.flags.dry_run);
+        assert_eq!(resolved.flags.movie_template, "Movies");
+        assert_eq!(resolved.flags.tv_template, "2024");
+        assert_eq!(
+            resolved.sources["threads"],
+            Source::Config(config_path.clone())
+        );
+        assert_eq!(resolved.sources["inputs"], Source::Profile("Angel".into()));
+        assert_eq!(
+            resolved.sources["tv-template"],
+            Source::Env("MEDIASORT_TV_TEMPLATE".into())
+        );
+        assert_eq!(resolved.sources["output"], Source::Cli);
+        assert_eq!(resolved.sources["dry-run"], Source::Cli);
+
+        let mut cli = serde_json::Map::new();
+        cli.insert("threads".into(), serde_json::json!("many"));
+        assert!(resolve_from(config_path, env_var, None, cli).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_profile_flags_inheritance() {
+        let mut parent = ProfileFlags::default();
+        parent.set_from_str("recursive=true").unwrap();
+        parent.set_from_str("tv-template=Shows").unwrap();
+
+        let mut child = ProfileFlags::default();
+        child.set_from_str("tv-template=Anime").unwrap();
+        child.set_from_str("search=true").unwrap();
+
+        child.inherit(&parent).unwrap();
+        assert_eq!(child.recursive, Some(true));
+        assert_eq!(child.tv_template.as_deref(), Some("Anime"));
+        assert_eq!(child.search, Some(true));
+        assert_eq!(child.verbose, None);
+    }
+
+    #[test]
+    fn test_route_matching() {
+        let route = Route {
+            pattern: Some(Pattern::new(r"(?i)vostfr").unwrap()),
+            media: Some(MediaType::Series),
+            output: Some("/anime".into()),
+            ..Default::default()
+        };
+
+        let episode = Episode::new_test("Spy.x.Family.S02E01.VOSTFR.1080p.mkv", false);
+        assert!(route.matches(&episode));
+        let movie = Episode::new_test("Suzume.2022.VOSTFR.1080p.mkv", true);
+        assert!(!route.matches(&movie));
+        let episode = Episode::new_test("Friends.S02E15.1080p.mp4", false);
+        assert!(!route.matches(&episode));
+
+        let route = Route {
+            input: Some("/downloads/anime".into()),
+            output: Some("/anime".into()),
+            ..Default::default()
+        };
+        let mut episode = Episode::new_test("Frieren.S01E05.mkv", false);
+        assert!(!route.matches(&episode));
+        episode.full_path = "/downloads/anime/Frieren.S01E05.mkv".into();
+        assert!(route.matches(&episode));
+    }
+
+    #[test]
+    fn test_route_conditions() {
+        let uhd = Route {
+            min_resolution: Some(2160),
+            template: Some("UHD".to_string()),
+            ..Default::default()
+        };
+        assert!(uhd.matches(&Episode::new_test("Dune.Part.One.2021.2160p.mkv", true)));
+        assert!(uhd.matches(&Episode::new_test("Interstellar.2014.4K.mkv", true)));
+        assert!(!uhd.matches(&Episode::new_test("Loki.S01E02.1080p.mkv", false)));
+        assert!(!uhd.matches(&Episode::new_test("Loki.S01E02.mkv", false)));
+
+        let kids = Route {
+            genre: Some("kids".to_string()),
+            language: Some("multi".to_string()),
+            output: Some("/kids".into()),
+            ..Default::default()
+        };
+        let mut episode = Episode::new_test("Bluey.S01E01.MULTI.1080p.mkv", false);
+        assert_eq!(
+            kids.check(&episode),
+            Err("genres [] do not include \"kids\"".to_string())
+        );
+        episode.genres = vec!["Kids".to_string(), "Animation".to_string()];
+        assert!(kids.matches(&episode));
+
+        let season = Route {
+            title: Some(Pattern::new("^Bluey$").unwrap()),
+            season: Some(2),
+            output: Some("/kids".into()),
+            ..Default::default()
+        };
+        assert!(!season.matches(&episode));
+        assert!(season.matches(&Episode::new_test("Bluey.S02E03.mkv", false)));
+
+        assert!(Route::default().validate().is_err());
+        let invalid = toml::from_str::<Route>("pattern = \"S(\"\noutput = \"/x\"");
+        assert!(invalid
+            .unwrap_err()
+            .to_string()
+            .contains("Invalid route pattern"));
+    }
+
+    #[test]
+    fn test_release_info() {
+        let release = ReleaseInfo::parse(
+            "Dune.Part.Two.2024.Extended.2160p.UHD.BluRay.REMUX.DV.HDR10.TrueHD.Atmos.7.1.x265-FraMeSToR.mkv",
+        );
+        assert_eq!(release.resolution, Some(2160));
+        assert_eq!(release.source.as_deref(), Some("Remux"));
+        assert_eq!(release.video_codec.as_deref(), Some("H.265"));
+        assert_eq!(release.audio_codec.as_deref(), Some("TrueHD Atmos"));
+        assert_eq!(release.audio_channels.as_deref(), Some("7.1"));
+        assert_eq!(release.hdr.as_deref(), Some("DV HDR10"));
+        assert_eq!(release.edition.as_deref(), Some("Extended"));
+        assert_eq!(release.group.as_deref(), Some("FraMeSToR"));
+
+        let release =
+            ReleaseInfo::parse("The.Office.S03E05.REPACK.720p.WEB-DL.DDP5.1.H.264-NTb.mkv");
+        assert_eq!(release.source.as_deref(), Some("WEB-DL"));
+        assert_eq!(release.video_codec.as_deref(), Some("H.264"));
+        assert_eq!(release.audio_codec.as_deref(), Some("EAC3"));
+        assert_eq!(release.audio_channels.as_deref(), Some("5.1"));
+        assert!(release.repack && !release.proper);
+        assert!(release.rank() < ReleaseInfo::parse("The.Office.S03E05.1080p.HDTV.mkv").rank());
+
+        let release = ReleaseInfo::parse("[SubsPlease] Frieren - 05 (1080p) [VOSTFR].mkv");
+        assert_eq!(release.group.as_deref(), Some("SubsPlease"));
+        assert_eq!(release.languages, vec!["VOSTFR"]);
+        assert_eq!(ReleaseInfo::parse("Spider-Man.mkv").group, None);
+
+        let release = ReleaseInfo::parse("Heat.1995.1080p.BluRay.x264.mkv");
+        assert_eq!(
+            release.format_template("Films/{resolution} {hdr}/{edition}"),
+            "Films/1080p"
+        );
+        assert_eq!(
+            ReleaseInfo::parse("Heat.1995.Director's.Cut.mkv").format_template("Films/{edition}"),
+            "Films/Director's Cut"
+        );
+        assert_eq!(
+            ReleaseInfo::parse("Dune.2021.2160p.BluRay.DTS-X.7.1.mkv")
+                .format_template("Films/{audio} {channels}"),
+            "Films/DTSX 7.1"
+        );
+
+        let route = Route {
+            group: Some("ntb".to_string()),
+            output: Some("/web".into()),
+            ..Default::default()
+        };
+        assert!(route.matches(&Episode::new_test(
+            "The.Office.S03E05.720p.WEB-DL.x264-NTb.mkv",
+            false
+        )));
+        assert!(!route.matches(&Episode::new_test("The.Office.S03E05.720p.mkv", false)));
+    }
+
+    #[test]
+    fn test_media_info() {
+        use ffprobe::{FfProbe, Format, Stream, StreamTags};
+
+        let probe = FfProbe {
+            streams: vec![
+                Stream {
+                    index: 0,
+                    codec_type: Some("video".to_string()),
+                    codec_name: Some("h264".to_string()),
+                    width: Some(1920),
+                    height: Some(800),
+                    ..Default::default()
+                },
+                Stream {
+                    index: 1,
+                    codec_type: Some("audio".to_string()),
+                    codec_name: Some("eac3".to_string()),
+                    channels: Some(6),
+                    tags: Some(StreamTags {
+                        language: Some("fre".to_string()),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                },
+                Stream {
+                    index: 2,
+                    codec_type: Some("subtitle".to_string()),
+                    codec_name: Some("subrip".to_string()),
+                    ..Default::default()
+                },
+            ],
+            format: Format {
+                duration: Some("7260.5".to_string()),
+                ..Default::default()
+            },
+        };
+
+        let info = MediaInfo::from_ffprobe(&probe);
+        assert_eq!(info.duration, Some(7260.5));
+        assert_eq!(info.resolution(), Some(1080));
+        assert_eq!(info.audio_channels().as_deref(), Some("5.1"));
+        assert_eq!(info.audio_tracks[0].language.as_deref(), Some("fre"));
+        assert_eq!(info.subtitle_tracks.len(), 1);
+
+        let mut release = ReleaseInfo::parse("Heat.1995.2160p.x264.mkv");
+        let conflicts = release.apply_media_info(&info);
+        assert_eq!(release.resolution, Some(1080));
+        assert_eq!(release.audio_channels.as_deref(), Some("5.1"));
+        assert_eq!(conflicts, vec!["resolution is 1080p, not 2160p as named"]);
+
+        let mut cache = ProbeCache::default();
+        let path = PathBuf::from("/movies/Heat.1995.mkv");
+        let key = FileKey {
+            size: 10,
+            mtime: 20,
+        };
+        cache.insert(&path, key.clone(), info.clone());
+        assert_eq!(cache.get(&path, &key), Some(info.clone()));
+        assert_eq!(
+            cache.get(
+                &path,
+                &FileKey {
+                    size: 11,
+                    mtime: 20
+                }
+            ),
+            None
+        );
+
+        let dir = std::env::temp_dir().join("mediasort_probe_cache");
+        std::fs::create_dir_all(&dir).unwrap();
+        let existing = dir.join("Heat.1995.mkv");
+        std::fs::write(&existing, "").unwrap();
+        cache.insert(&existing, key.clone(), info.clone());
+        let cache_path = dir.join("probe_cache.json");
+        cache.save(&cache_path).unwrap();
+        let saved = ProbeCache::load(&cache_path).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(saved.get(&existing, &key), Some(info));
+        assert_eq!(saved.get(&path, &key), None);
+    }
+
+    #[test]
+    fn test_unprobed_media() {
+        let path = std::env::temp_dir().join("MediaSort.Test.S01E02.mkv");
+        std::fs::write(&path, b"not a video").unwrap();
+
+        let episode = Episode::new(&path, None).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(episode.probe_error.is_some());
+        assert!(episode.media_info.is_none());
+        assert!(!episode.is_movie);
+        assert_eq!(episode.season, 1);
+        assert_eq!(episode.episode, 2);
+    }
+
+    #[test]
+    fn test_unparsable_files() {
+        let path = PathBuf::from("/downloads/Some Show S01E01");
+        assert_eq!(
+            Episode::new(&path, None).err(),
+            Some(ParseError::NoExtension(path.clone()))
+        );
+
+        let path = PathBuf::from("/downloads/S01E01.srt");
+        assert_eq!(
+            Subtitle::new(path).err(),
+            Some(ParseError::NoName("S01E01.srt".to_string()))
+        );
+
+        let path = PathBuf::from("/");
+        assert_eq!(
+            Episode::new(&path, None).err(),
+            Some(ParseError::NoFileName(path.clone()))
+        );
+    }
+
+    #[test]
+    fn test_report() {
+        let episode = Episode::new_test("Friends.S02E15.1080p.mp4", false);
+        let rows = vec![
+            ReportRow::from_episode(
+                &episode,
+                Action::Moved,
+                Some("/series/Friends/S02/Friends - E15.mp4".into()),
+                Duration::from_millis(12),
+            ),
+            ReportRow::from_error(
+                &PathBuf::from("/downloads/S01E01"),
+                anyhow::anyhow!("no extension"),
+                Duration::ZERO,
+            ),
+        ];
+        assert_eq!(rows[0].media_type, Some(FileType::Series));
+        assert_eq!(rows[0].season, Some(2));
+        assert_eq!(rows[1].action, Action::Error);
+
+        let json_path = std::env::temp_dir().join("mediasort_report.json");
+        write_report(&json_path, &rows).unwrap();
+        let json: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&json_path).unwrap()).unwrap();
+        std::fs::remove_file(&json_path).unwrap();
+        assert_eq!(json[0]["type"], "series");
+        assert_eq!(json[0]["action"], "moved");
+        assert_eq!(json[0]["duration_ms"], 12);
+        assert_eq!(json[1]["reason"], "no extension");
+
+        let csv_path = std::env::temp_dir().join("mediasort_report.csv");
+        write_report(&csv_path, &rows).unwrap();
+        let csv = std::fs::read_to_string(&csv_path).unwrap();
+        std::fs::remove_file(&csv_path).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(
+            lines[0].starts_with("source,type,name,season,episode,year,provider,confidence,action")
+        );
+        assert!(lines[1].contains(",series,Friends,2,15,,,,moved,"));
+        assert!(lines[2].ends_with(",error,,no extension,0"));
+
+        let dir = std::env::temp_dir().join("mediasort_report_year");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("Movie.2010.mkv");
+        std::fs::write(&path, b"").unwrap();
+        let movie = Episode::new(&path, None).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        let row = ReportRow::from_episode(&movie, Action::Moved, None, Duration::ZERO);
+        assert_eq!(row.media_type, Some(FileType::Movie));
+        assert_eq!(row.year, Some(2010));
+    }
 }
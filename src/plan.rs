@@ -0,0 +1,212 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::probe::FileKey;
+use crate::report::{Action, ReportRow};
+
+pub const PLAN_VERSION: u32 = 1;
+
+/// Every move of a dry run, to be reviewed then executed with `apply --plan`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Plan {
+    pub version: u32,
+    pub moves: Vec<PlannedMove>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PlannedMove {
+    pub source: PathBuf,
+    pub destination: PathBuf,
+    /// movie, series, subtitle, sidecar, or duplicate when moved to the trash folder.
+    #[serde(rename = "type")]
+    pub media_type: String,
+    /// Size and modification time of the source when the plan was made.
+    #[serde(flatten)]
+    pub key: FileKey,
+    /// The destination already existed, the move is skipped.
+    pub conflict: bool,
+}
+
+impl PlannedMove {
+    pub fn new(source: &Path, destination: PathBuf, media_type: &str) -> Result<Self> {
+        Ok(Self {
+            source: source.to_path_buf(),
+            key: FileKey::new(source)?,
+            conflict: destination.exists(),
+            destination,
+            media_type: media_type.to_string(),
+        })
+    }
+
+    /// Why the source cannot be moved as planned anymore.
+    pub fn check_source(&self) -> std::result::Result<(), String> {
+        match FileKey::new(&self.source) {
+            Ok(key) if key == self.key => Ok(()),
+            Ok(_) => Err("changed since the plan was made".to_string()),
+            Err(_) => Err("does not exist anymore".to_string()),
+        }
+    }
+}
+
+impl Plan {
+    /// Moves are sorted by destination so the same inputs always give the same plan.
+    pub fn new(mut moves: Vec<PlannedMove>) -> Self {
+        moves.sort_by(|a, b| {
+            a.destination
+                .cmp(&b.destination)
+                .then_with(|| a.source.cmp(&b.source))
+        });
+
+        Self {
+            version: PLAN_VERSION,
+            moves,
+        }
+    }
+
+    /// Moves putting back every file a run moved, from its report rows. Replaced
+    /// library copies are not restored.
+    pub fn undo(rows: &[ReportRow]) -> Result<Self> {
+        let mut moves = Vec::new();
+        for row in rows {
+            let Some(destination) = &row.destination else {
+                continue;
+            };
+            let media_type = match row.action {
+                Action::Moved => row
+                    .media_type
+                    .map(|media_type| media_type.as_str())
+                    .unwrap_or_default(),
+                Action::Discarded => "duplicate",
+                _ => continue,
+            };
+            moves.push(PlannedMove::new(
+                destination,
+                row.source.clone(),
+                media_type,
+            )?);
+        }
+
+        Ok(Self::new(moves))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let content = serde_json::to_string_pretty(self)?;
+        fs::write(path, content).with_context(|| format!("Could not write plan {:?}", path))
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let content =
+            fs::read_to_string(path).with_context(|| format!("Could not read plan {:?}", path))?;
+        let plan: Plan =
+            serde_json::from_str(&content).with_context(|| format!("Invalid plan {:?}", path))?;
+
+        if plan.version != PLAN_VERSION {
+            bail!(
+                "Unsupported plan version {} in {:?}, expected {}",
+                plan.version,
+                path,
+                PLAN_VERSION
+            );
+        }
+
+        Ok(plan)
+    }
+
+    /// Fail when any source was moved, removed or modified since the plan was made.
+    pub fn check_sources(&self) -> Result<()> {
+        let changed: Vec<String> = self
+            .moves
+            .iter()
+            .filter_map(|planned| {
+                planned
+                    .check_source()
+                    .err()
+                    .map(|reason| format!("  - {:?}: {}", planned.source, reason))
+            })
+            .collect();
+
+        if !changed.is_empty() {
+            bail!(
+                "Refusing to apply the plan, {} sources changed:\n{}",
+                changed.len(),
+                changed.join("\n")
+            );
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    use crate::episode::Episode;
+
+    #[test]
+    fn test_plan() {
+        let dir = std::env::temp_dir().join("mediasort_plan");
+        std::fs::create_dir_all(&dir).unwrap();
+        let movie = dir.join("Movie.2020.mkv");
+        let episode = dir.join("Show.S01E01.mkv");
+        std::fs::write(&movie, "movie").unwrap();
+        std::fs::write(&episode, "episode").unwrap();
+
+        let plan = Plan::new(vec![
+            PlannedMove::new(&episode, "/series/Show/S01/Show - E01.mkv".into(), "series").unwrap(),
+            PlannedMove::new(&movie, "/films/Movie.mkv".into(), "movie").unwrap(),
+        ]);
+        assert_eq!(plan.moves[0].media_type, "movie");
+        assert!(!plan.moves[0].conflict);
+
+        let plan_path = dir.join("plan.json");
+        std::fs::write(&plan_path, serde_json::to_string_pretty(&plan).unwrap()).unwrap();
+        let loaded = Plan::load(&plan_path).unwrap();
+        assert_eq!(loaded, plan);
+        assert!(loaded.check_sources().is_ok());
+
+        std::fs::write(&movie, "another movie").unwrap();
+        std::fs::remove_file(&episode).unwrap();
+        let error = loaded.check_sources().unwrap_err().to_string();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(error.contains("2 sources changed"));
+        assert!(error.contains("changed since the plan was made"));
+        assert!(error.contains("does not exist anymore"));
+    }
+
+    #[test]
+    fn test_undo() {
+        let dir = std::env::temp_dir().join("mediasort_undo");
+        std::fs::create_dir_all(&dir).unwrap();
+        let destination = dir.join("Show - E01.mkv");
+        std::fs::write(&destination, "").unwrap();
+
+        let episode = Episode::new_test("Show.S01E01.mkv", false);
+        let rows = vec![
+            ReportRow::from_episode(
+                &episode,
+                Action::Moved,
+                Some(destination.clone()),
+                Duration::ZERO,
+            ),
+            ReportRow::from_episode(
+                &episode,
+                Action::Skipped,
+                Some(destination.clone()),
+                Duration::ZERO,
+            )
+            .with_reason("already in place"),
+        ];
+        let undo = Plan::undo(&rows).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(undo.moves.len(), 1);
+        assert_eq!(undo.moves[0].source, destination);
+        assert_eq!(undo.moves[0].destination, episode.full_path);
+        assert_eq!(undo.moves[0].media_type, "series");
+    }
+}
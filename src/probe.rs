@@ -0,0 +1,287 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+use std::time::UNIX_EPOCH;
+
+use anyhow::{bail, Context, Result};
+use directories::BaseDirs;
+use ffprobe::{ffprobe, FfProbe, Stream};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+
+static CACHE: Lazy<Mutex<ProbeCache>> = Lazy::new(|| {
+    let cache = get_cache_path().and_then(|path| ProbeCache::load(&path).ok());
+    Mutex::new(cache.unwrap_or_default())
+});
+
+/// Whether an `ffprobe` binary can be run, checked once per run.
+static AVAILABLE: Lazy<bool> = Lazy::new(|| {
+    Command::new("ffprobe")
+        .arg("-version")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map(|status| status.success())
+        .unwrap_or(false)
+});
+
+pub fn is_available() -> bool {
+    *AVAILABLE
+}
+
+/// What ffprobe found inside a media file.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct MediaInfo {
+    /// Duration in seconds.
+    pub duration: Option<f64>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub video_codec: Option<String>,
+    /// DV, HDR10 or HDR, from the color metadata of the video stream.
+    pub hdr: Option<String>,
+    pub audio_tracks: Vec<AudioTrack>,
+    pub subtitle_tracks: Vec<SubtitleTrack>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct AudioTrack {
+    pub index: i64,
+    pub codec: Option<String>,
+    pub language: Option<String>,
+    pub channels: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct SubtitleTrack {
+    pub index: i64,
+    pub codec: Option<String>,
+    pub language: Option<String>,
+    pub forced: bool,
+    pub hearing_impaired: bool,
+}
+
+impl MediaInfo {
+    pub fn from_ffprobe(probe: &FfProbe) -> Self {
+        let mut info = MediaInfo {
+            duration: probe
+                .format
+                .duration
+                .as_ref()
+                .and_then(|duration| duration.parse::<f64>().ok()),
+            ..Default::default()
+        };
+
+        for stream in &probe.streams {
+            match stream.codec_type.as_deref() {
+                Some("video") if stream.disposition.attached_pic == 0 && info.width.is_none() => {
+                    info.width = stream.width.map(|width| width as u32);
+                    info.height = stream.height.map(|height| height as u32);
+                    info.video_codec = stream.codec_name.as_deref().map(normalize_codec);
+                    info.hdr = hdr_format(stream);
+                }
+                Some("audio") => info.audio_tracks.push(AudioTrack {
+                    index: stream.index,
+                    codec: stream.codec_name.clone(),
+                    language: stream_language(stream),
+                    channels: stream.channels.map(|channels| channels as u32),
+                }),
+                Some("subtitle") => info.subtitle_tracks.push(SubtitleTrack {
+                    index: stream.index,
+                    codec: stream.codec_name.clone(),
+                    language: stream_language(stream),
+                    forced: stream.disposition.forced != 0,
+                    hearing_impaired: stream.disposition.hearing_impaired != 0,
+                }),
+                _ => {}
+            }
+        }
+
+        info
+    }
+
+    /// Resolution class of the video stream, e.g. a 1920x800 movie is 1080.
+    /// The width is used first so letterboxed movies are not downgraded.
+    pub fn resolution(&self) -> Option<u32> {
+        let width = self.width?;
+        let height = self.height.unwrap_or(0);
+        Some(match () {
+            _ if width >= 3200 || height >= 2000 => 2160,
+            _ if width >= 2200 || height >= 1300 => 1440,
+            _ if width >= 1800 || height >= 1000 => 1080,
+            _ if width >= 1200 || height >= 700 => 720,
+            _ if height >= 560 => 576,
+            _ if height >= 460 => 480,
+            _ => height,
+        })
+    }
+
+    /// Layout of the first audio track, e.g. 6 channels is 5.1.
+    pub fn audio_channels(&self) -> Option<String> {
+        let channels = self.audio_tracks.first()?.channels?;
+        Some(match channels {
+            1 => "1.0".to_string(),
+            2 => "2.0".to_string(),
+            channels => format!("{}.1", channels - 1),
+        })
+    }
+}
+
+fn stream_language(stream: &Stream) -> Option<String> {
+    stream
+        .tags
+        .as_ref()
+        .and_then(|tags| tags.language.clone())
+        .filter(|language| language != "und")
+}
+
+fn normalize_codec(codec: &str) -> String {
+    match codec {
+        "h264" => "H.264".to_string(),
+        "hevc" => "H.265".to_string(),
+        "av1" => "AV1".to_string(),
+        "vp9" => "VP9".to_string(),
+        codec => codec.to_uppercase(),
+    }
+}
+
+fn hdr_format(stream: &Stream) -> Option<String> {
+    let side_data = |name: &str| {
+        stream
+            .side_data_list
+            .iter()
+            .any(|side_data| side_data.side_data_type.contains(name))
+    };
+
+    if side_data("DOVI") {
+        Some("DV".to_string())
+    } else if side_data("Mastering display") || side_data("Content light level") {
+        Some("HDR10".to_string())
+    } else if stream
+        .color_space
+        .as_deref()
+        .is_some_and(|space| space.starts_with("bt2020"))
+        && stream
+            .pix_fmt
+            .as_deref()
+            .is_some_and(|format| format.contains("10"))
+    {
+        Some("HDR".to_string())
+    } else {
+        None
+    }
+}
+
+/// Probe a media file, reusing the cached result while its size and modification time
+/// are unchanged.
+pub fn probe(path: &Path) -> Result<MediaInfo> {
+    if !is_available() {
+        bail!("ffprobe is not available");
+    }
+
+    let key = FileKey::new(path)?;
+
+    if let Some(info) = CACHE.lock().unwrap().get(path, &key) {
+        return Ok(info);
+    }
+
+    let probe = ffprobe(path).with_context(|| format!("Could not probe {:?}", path))?;
+    let info = MediaInfo::from_ffprobe(&probe);
+
+    CACHE.lock().unwrap().insert(path, key, info.clone());
+
+    Ok(info)
+}
+
+/// Write the probe results of this run to the cache file. Without a home directory there
+/// is no cache, and nothing is written.
+pub fn save_cache() -> Result<()> {
+    match get_cache_path() {
+        Some(path) => CACHE.lock().unwrap().save(&path),
+        None => Ok(()),
+    }
+}
+
+fn get_cache_path() -> Option<PathBuf> {
+    let base_dirs = BaseDirs::new()?;
+    Some(
+        base_dirs
+            .data_local_dir()
+            .join("MediaSort")
+            .join("probe_cache.json"),
+    )
+}
+
+/// Size and modification time, a probe result is only reused when both still match.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FileKey {
+    pub size: u64,
+    pub mtime: u64,
+}
+
+impl FileKey {
+    pub fn new(path: &Path) -> Result<Self> {
+        let metadata =
+            fs::metadata(path).with_context(|| format!("Could not read metadata of {:?}", path))?;
+        let mtime = metadata
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .map(|mtime| mtime.as_secs())
+            .unwrap_or(0);
+
+        Ok(Self {
+            size: metadata.len(),
+            mtime,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct CacheEntry {
+    key: FileKey,
+    info: MediaInfo,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ProbeCache {
+    entries: HashMap<PathBuf, CacheEntry>,
+    #[serde(skip)]
+    changed: bool,
+}
+
+impl ProbeCache {
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    /// Write the cache, without the files that do not exist anymore, e.g. moved by a sort.
+    pub fn save(&mut self, path: &Path) -> Result<()> {
+        let count = self.entries.len();
+        self.entries.retain(|source, _| source.exists());
+        if !self.changed && self.entries.len() == count {
+            return Ok(());
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_string(self)?)
+            .with_context(|| format!("Could not write probe cache {:?}", path))?;
+        self.changed = false;
+        Ok(())
+    }
+
+    pub fn get(&self, path: &Path, key: &FileKey) -> Option<MediaInfo> {
+        self.entries
+            .get(path)
+            .filter(|entry| &entry.key == key)
+            .map(|entry| entry.info.clone())
+    }
+
+    pub fn insert(&mut self, path: &Path, key: FileKey, info: MediaInfo) {
+        self.entries
+            .insert(path.to_path_buf(), CacheEntry { key, info });
+        self.changed = true;
+    }
+}
@@ -0,0 +1,152 @@
+use serde::{Deserialize, Serialize};
+
+use crate::duplicate::MediaCopy;
+
+/// What makes a copy better than another, used by `--upgrade` to decide whether a new
+/// release replaces the copy already in the library.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct QualityProfile {
+    /// Compared in order, the first one that differs decides.
+    pub criteria: Vec<Criterion>,
+    /// Highest resolution worth upgrading to, e.g. 1080 to never replace a copy by a 4K one.
+    pub max_resolution: Option<u32>,
+    /// Bitrates closer than this percentage are considered equal.
+    pub min_bitrate_gain: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Criterion {
+    Resolution,
+    /// Remux, BluRay, WEB-DL, WEBRip, HDTV, HDRip then DVD.
+    Source,
+    Hdr,
+    /// AV1, then H.265 and VP9, then H.264.
+    Codec,
+    Channels,
+    Bitrate,
+    Size,
+}
+
+impl Default for QualityProfile {
+    fn default() -> Self {
+        Self {
+            criteria: vec![
+                Criterion::Resolution,
+                Criterion::Hdr,
+                Criterion::Source,
+                Criterion::Bitrate,
+            ],
+            max_resolution: None,
+            min_bitrate_gain: 10,
+        }
+    }
+}
+
+impl QualityProfile {
+    /// Whether `new` should replace `old`. A criterion is skipped when it is unknown for
+    /// either copy, e.g. the source of a sorted file, so when in doubt nothing is replaced.
+    pub fn is_upgrade(&self, new: &MediaCopy, old: &MediaCopy) -> bool {
+        for criterion in &self.criteria {
+            let (Some(new_value), Some(old_value)) =
+                (self.value(*criterion, new), self.value(*criterion, old))
+            else {
+                continue;
+            };
+
+            if *criterion == Criterion::Bitrate {
+                let gain = 100 + self.min_bitrate_gain as u64;
+                if new_value * 100 > old_value * gain {
+                    return true;
+                } else if old_value * 100 > new_value * gain {
+                    return false;
+                }
+            } else if new_value != old_value {
+                return new_value > old_value;
+            }
+        }
+
+        false
+    }
+
+    fn value(&self, criterion: Criterion, copy: &MediaCopy) -> Option<u64> {
+        let release = &copy.release;
+        match criterion {
+            Criterion::Resolution => release.resolution.map(|resolution| {
+                let max_resolution = self.max_resolution.unwrap_or(u32::MAX);
+                resolution.min(max_resolution) as u64
+            }),
+            Criterion::Source => release.source.as_ref().map(|_| release.rank().1 as u64),
+            // Without a probe, a sorted file has no HDR tag to tell
+            Criterion::Hdr => {
+                (!copy.in_library || copy.probed).then_some(release.hdr.is_some() as u64)
+            }
+            Criterion::Codec => release.video_codec.as_deref().map(codec_rank),
+            Criterion::Channels => release.audio_channels.as_deref().map(channels_rank),
+            Criterion::Bitrate => copy.bitrate,
+            Criterion::Size => Some(copy.key.size),
+        }
+    }
+}
+
+fn codec_rank(codec: &str) -> u64 {
+    match codec {
+        "AV1" => 3,
+        "H.265" | "VP9" => 2,
+        "H.264" => 1,
+        _ => 0,
+    }
+}
+
+/// `5.1` gives 51, `2.0` gives 20.
+fn channels_rank(channels: &str) -> u64 {
+    channels.replace('.', "").parse().unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    use crate::config::profile::ProfileFlags;
+    use crate::probe::FileKey;
+    use crate::release::ReleaseInfo;
+
+    #[test]
+    fn test_quality_upgrade() {
+        let copy = |name: &str, in_library: bool, probed: bool, bitrate: Option<u64>| MediaCopy {
+            path: PathBuf::from(name),
+            in_library,
+            probed,
+            key: FileKey { size: 1, mtime: 0 },
+            release: ReleaseInfo::parse(name),
+            bitrate,
+        };
+        let quality = QualityProfile::default();
+
+        let new = copy("Show.S01E01.1080p.WEB-DL.mkv", false, true, Some(5_000_000));
+        let old = copy("Show - E01.720p.mkv", true, true, Some(8_000_000));
+        assert!(quality.is_upgrade(&new, &old));
+        assert!(!quality.is_upgrade(&old, &new));
+
+        // Nothing is known about a sorted file that could not be probed
+        let unprobed = copy("Show - E01.mkv", true, false, None);
+        assert!(!quality.is_upgrade(&new, &unprobed));
+
+        let same = copy("Show - E01.1080p.mkv", true, true, Some(4_800_000));
+        assert!(!quality.is_upgrade(&new, &same));
+        let worse = copy("Show - E01.1080p.mkv", true, true, Some(3_000_000));
+        assert!(quality.is_upgrade(&new, &worse));
+
+        let mut flags = ProfileFlags::default();
+        flags
+            .set_from_str(r#"quality={"criteria": ["resolution"], "max-resolution": 1080}"#)
+            .unwrap();
+        let quality = flags.quality.unwrap();
+        assert_eq!(quality.criteria, vec![Criterion::Resolution]);
+        let uhd = copy("Show.S01E01.2160p.mkv", false, false, None);
+        assert!(!quality.is_upgrade(&uhd, &same));
+        assert!(quality.is_upgrade(&uhd, &old));
+    }
+}
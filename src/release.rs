@@ -0,0 +1,295 @@
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::probe::MediaInfo;
+use crate::search::strings::*;
+
+/// Release details parsed from a file name, e.g. `Movie.2019.2160p.WEB-DL.DDP5.1.HDR.x265-GROUP`.
+#[derive(Serialize, Debug, Clone, PartialEq, Default)]
+pub struct ReleaseInfo {
+    /// Vertical resolution, e.g. 1080.
+    pub resolution: Option<u32>,
+    /// Remux, BluRay, WEB-DL, WEBRip, HDTV, DVD...
+    pub source: Option<String>,
+    pub video_codec: Option<String>,
+    pub audio_codec: Option<String>,
+    /// Audio layout, e.g. 5.1.
+    pub audio_channels: Option<String>,
+    /// DV, HDR10+, HDR10, HDR or HLG.
+    pub hdr: Option<String>,
+    pub group: Option<String>,
+    /// Extended, Director's Cut, IMAX...
+    pub edition: Option<String>,
+    pub repack: bool,
+    pub proper: bool,
+    /// Language tags like VOSTFR or MULTI.
+    pub languages: Vec<String>,
+}
+
+impl ReleaseInfo {
+    pub fn parse(filename: &str) -> Self {
+        let stem = Path::new(filename)
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or(filename);
+        let name = stem.replace('_', ".");
+
+        let mut release = ReleaseInfo {
+            resolution: parse_resolution(&name),
+            source: parse_source(&name),
+            video_codec: first_match(&VIDEO_CODEC, &name)
+                .map(|codec| normalize_video_codec(&codec)),
+            audio_codec: first_match(&AUDIO_CODEC, &name)
+                .map(|codec| normalize_audio_codec(&codec)),
+            audio_channels: first_match(&AUDIO_CHANNELS, &name),
+            hdr: parse_hdr(&name),
+            group: None,
+            edition: first_match(&EDITION, &name).map(|edition| normalize_edition(&edition)),
+            repack: REPACK.is_match(&name),
+            proper: PROPER.is_match(&name),
+            languages: LANGUAGES
+                .find_iter(&name)
+                .map(|language| language.as_str().to_uppercase())
+                .collect(),
+        };
+
+        if ATMOS.is_match(&name) {
+            release.audio_codec = Some(match release.audio_codec {
+                Some(codec) => format!("{} Atmos", codec),
+                None => "Atmos".to_string(),
+            });
+        }
+
+        // A trailing `-WORD` is only a group when the name looks like a release,
+        // otherwise `Spider-Man.mkv` would be released by "Man".
+        if let Some(captures) = GROUP.captures(stem) {
+            release.group = match (captures.get(1), captures.get(2)) {
+                (Some(group), _) => Some(group.as_str().to_string()),
+                (None, Some(group)) if release.is_release_name() => {
+                    Some(group.as_str().to_string())
+                }
+                _ => None,
+            };
+        }
+
+        release
+    }
+
+    /// Replace the guesses from the file name with what the file really contains,
+    /// returning a message for each value that disagreed. The HDR format is only
+    /// replaced when found, as the stream metadata is not always complete.
+    pub fn apply_media_info(&mut self, info: &MediaInfo) -> Vec<String> {
+        let mut conflicts = Vec::new();
+
+        if let Some(resolution) = info.resolution() {
+            if let Some(named) = self.resolution.filter(|&named| named != resolution) {
+                conflicts.push(format!(
+                    "resolution is {}p, not {}p as named",
+                    resolution, named
+                ));
+            }
+            self.resolution = Some(resolution);
+        }
+
+        for (field, probed) in [
+            (&mut self.video_codec, &info.video_codec),
+            (&mut self.audio_channels, &info.audio_channels()),
+            (&mut self.hdr, &info.hdr),
+        ] {
+            if let Some(probed) = probed {
+                if let Some(named) = field.as_ref().filter(|named| *named != probed) {
+                    conflicts.push(format!("{} found in file, not {} as named", probed, named));
+                }
+                *field = Some(probed.clone());
+            }
+        }
+
+        conflicts
+    }
+
+    fn is_release_name(&self) -> bool {
+        self.resolution.is_some() || self.source.is_some() || self.video_codec.is_some()
+    }
+
+    /// Ordering key from worst to best copy: resolution, source, HDR, then repacks and propers.
+    pub fn rank(&self) -> (u32, u32, bool, bool) {
+        (
+            self.resolution.unwrap_or(0),
+            self.source.as_deref().map(source_rank).unwrap_or(0),
+            self.hdr.is_some(),
+            self.repack || self.proper,
+        )
+    }
+
+    /// Value of a template placeholder, e.g. `resolution` gives `1080p`.
+    pub fn field(&self, key: &str) -> Option<String> {
+        match key {
+            "resolution" => self.resolution.map(|resolution| format!("{}p", resolution)),
+            "source" => self.source.clone(),
+            "codec" => self.video_codec.clone(),
+            "audio" => self.audio_codec.clone(),
+            "channels" => self.audio_channels.clone(),
+            "hdr" => self.hdr.clone(),
+            "group" => self.group.clone(),
+            "edition" => self.edition.clone(),
+            "languages" => Some(self.languages.join(" ")),
+            _ => None,
+        }
+    }
+
+    /// Replace `{resolution}`, `{source}`, `{codec}`, `{audio}`, `{channels}`, `{hdr}`,
+    /// `{group}`, `{edition}` and `{languages}` in a folder template. Unknown values are
+    /// left empty and empty folders are dropped, so `Films/{edition}` gives `Films`. Values are
+    /// sanitized, `DTS:X` gives `DTSX`.
+    pub fn format_template(&self, template: &str) -> String {
+        let mut formatted = template.to_string();
+        for key in TEMPLATE_KEYS {
+            let placeholder = format!("{{{}}}", key);
+            if formatted.contains(&placeholder) {
+                let value = sanitize_filename(&self.field(key).unwrap_or_default());
+                formatted = formatted.replace(&placeholder, &value);
+            }
+        }
+
+        formatted
+            .split('/')
+            .map(|part| part.split_whitespace().collect::<Vec<&str>>().join(" "))
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<String>>()
+            .join("/")
+    }
+}
+
+pub const TEMPLATE_KEYS: [&str; 9] = [
+    "resolution",
+    "source",
+    "codec",
+    "audio",
+    "channels",
+    "hdr",
+    "group",
+    "edition",
+    "languages",
+];
+
+fn first_match(regex: &regex::Regex, name: &str) -> Option<String> {
+    regex
+        .captures(name)
+        .and_then(|captures| captures.get(1))
+        .map(|value| value.as_str().to_string())
+}
+
+fn parse_resolution(name: &str) -> Option<u32> {
+    let captures = RESOLUTION.captures(name)?;
+    match captures.get(1) {
+        Some(height) => height.as_str().parse::<u32>().ok(),
+        None => Some(2160),
+    }
+}
+
+/// Best source tag of the name, so `BluRay.REMUX` is a remux.
+fn parse_source(name: &str) -> Option<String> {
+    SOURCE
+        .find_iter(name)
+        .map(|source| normalize_source(source.as_str()))
+        .max_by_key(|source| source_rank(source))
+}
+
+fn parse_hdr(name: &str) -> Option<String> {
+    let mut formats: Vec<&str> = Vec::new();
+    let mut start = 0;
+    // Restart right after each tag, as the separator after `DV` is also the one before `HDR10`.
+    while let Some(captures) = HDR.captures_at(name, start) {
+        start = captures.get(1).unwrap().end();
+        let format = match captures[1].to_uppercase().replace([' ', '.'], "").as_str() {
+            "DV" | "DOVI" | "DOLBYVISION" => "DV",
+            "HDR10+" | "HDR10PLUS" => "HDR10+",
+            "HDR10" => "HDR10",
+            "HLG" => "HLG",
+            _ => "HDR",
+        };
+        if !formats.contains(&format) {
+            formats.push(format);
+        }
+    }
+    (!formats.is_empty()).then(|| formats.join(" "))
+}
+
+fn normalize_source(source: &str) -> String {
+    match source.to_uppercase().replace('-', "").as_str() {
+        "REMUX" => "Remux",
+        "BLURAY" | "BDRIP" | "BRRIP" => "BluRay",
+        "WEBDL" | "WEB" => "WEB-DL",
+        "WEBRIP" => "WEBRip",
+        "HDTV" => "HDTV",
+        "DVDRIP" | "DVD" => "DVD",
+        "HDRIP" => "HDRip",
+        _ => "CAM",
+    }
+    .to_string()
+}
+
+fn source_rank(source: &str) -> u32 {
+    match source {
+        "Remux" => 7,
+        "BluRay" => 6,
+        "WEB-DL" => 5,
+        "WEBRip" => 4,
+        "HDTV" => 3,
+        "HDRip" => 2,
+        "DVD" => 1,
+        _ => 0,
+    }
+}
+
+fn normalize_video_codec(codec: &str) -> String {
+    match codec.to_uppercase().replace('.', "").as_str() {
+        "X264" | "H264" | "AVC" => "H.264",
+        "X265" | "H265" | "HEVC" => "H.265",
+        "XVID" => "XviD",
+        "DIVX" => "DivX",
+        "VP9" => "VP9",
+        _ => "AV1",
+    }
+    .to_string()
+}
+
+fn normalize_audio_codec(codec: &str) -> String {
+    match codec.to_uppercase().replace(['-', ' ', '.'], "").as_str() {
+        "TRUEHD" => "TrueHD",
+        "DTSHDMA" => "DTS-HD MA",
+        "DTSHD" => "DTS-HD",
+        "DTSX" => "DTS:X",
+        "DTS" => "DTS",
+        "EAC3" | "DDP" | "DD+" => "EAC3",
+        "AC3" | "DD" => "AC3",
+        "AAC" => "AAC",
+        "FLAC" => "FLAC",
+        "OPUS" => "Opus",
+        _ => "MP3",
+    }
+    .to_string()
+}
+
+fn normalize_edition(edition: &str) -> String {
+    let edition = edition.to_uppercase();
+    if edition.starts_with("EXTENDED") {
+        "Extended"
+    } else if edition.starts_with("DIRECTOR") {
+        "Director's Cut"
+    } else if edition.starts_with("THEATRICAL") {
+        "Theatrical"
+    } else if edition.starts_with("FINAL") {
+        "Final Cut"
+    } else {
+        match edition.as_str() {
+            "IMAX" => "IMAX",
+            "UNRATED" => "Unrated",
+            "REMASTERED" => "Remastered",
+            "UNCUT" => "Uncut",
+            _ => "Criterion",
+        }
+    }
+    .to_string()
+}
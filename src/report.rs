@@ -0,0 +1,217 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+
+use crate::episode::Episode;
+use crate::sidecar::SidecarFile;
+use crate::subtitle::Subtitle;
+
+/// What happened to an input file.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Moved,
+    /// Not moved, e.g. during a dry run.
+    Skipped,
+    /// Not moved because the destination already exists.
+    Conflict,
+    /// Replaced a worse copy already in the library.
+    Upgraded,
+    /// A better copy of the same media was kept, moved to the trash folder when set.
+    Discarded,
+    /// An embedded subtitle stream written next to its media.
+    Extracted,
+    /// A junk file, a sample or an extracted archive removed from the input.
+    Deleted,
+    Error,
+}
+
+/// What an input file is.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum FileType {
+    Movie,
+    Series,
+    Subtitle,
+    Sidecar,
+    Junk,
+    Archive,
+}
+
+impl FileType {
+    pub fn of(episode: &Episode) -> Self {
+        if episode.is_movie {
+            FileType::Movie
+        } else {
+            FileType::Series
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FileType::Movie => "movie",
+            FileType::Series => "series",
+            FileType::Subtitle => "subtitle",
+            FileType::Sidecar => "sidecar",
+            FileType::Junk => "junk",
+            FileType::Archive => "archive",
+        }
+    }
+}
+
+/// Why a file was skipped, for the steps that act on the rows after the moves.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Skip {
+    #[default]
+    No,
+    /// Already at its destination.
+    InPlace,
+    /// Left by a dry run, a real run would move it or replace a worse copy with it.
+    DryRun,
+}
+
+/// One row of the `--report` file, for one input file.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ReportRow {
+    pub source: PathBuf,
+    /// Empty when the file could not be parsed.
+    #[serde(rename = "type")]
+    pub media_type: Option<FileType>,
+    pub name: Option<String>,
+    pub season: Option<u32>,
+    pub episode: Option<u32>,
+    pub year: Option<u32>,
+    pub provider: Option<String>,
+    pub confidence: Option<i64>,
+    pub action: Action,
+    pub destination: Option<PathBuf>,
+    /// Why the file was not moved, or what it replaced.
+    pub reason: Option<String>,
+    pub duration_ms: u128,
+    #[serde(skip)]
+    pub skip: Skip,
+}
+
+impl ReportRow {
+    pub fn from_episode(
+        episode: &Episode,
+        action: Action,
+        destination: Option<PathBuf>,
+        elapsed: Duration,
+    ) -> Self {
+        let search_match = episode.search_match.as_ref();
+
+        Self {
+            source: episode.full_path.clone(),
+            media_type: Some(FileType::of(episode)),
+            name: Some(episode.name.clone()),
+            season: (!episode.is_movie).then_some(episode.season),
+            episode: (!episode.is_movie).then_some(episode.episode),
+            year: episode.year.filter(|&year| year > 0),
+            provider: search_match.map(|search_match| search_match.provider.clone()),
+            confidence: search_match.map(|search_match| search_match.confidence),
+            action,
+            destination,
+            reason: None,
+            duration_ms: elapsed.as_millis(),
+            skip: Skip::No,
+        }
+    }
+
+    pub fn from_subtitle(
+        subtitle: &Subtitle,
+        action: Action,
+        destination: Option<PathBuf>,
+        elapsed: Duration,
+    ) -> Self {
+        Self {
+            source: subtitle.full_path.clone(),
+            media_type: Some(FileType::Subtitle),
+            ..Self::from_episode(&subtitle.episode, action, destination, elapsed)
+        }
+    }
+
+    pub fn from_sidecar(
+        sidecar: &SidecarFile,
+        action: Action,
+        destination: Option<PathBuf>,
+        elapsed: Duration,
+    ) -> Self {
+        Self {
+            source: sidecar.full_path.clone(),
+            media_type: Some(FileType::Sidecar),
+            ..Self::from_episode(&sidecar.episode, action, destination, elapsed)
+        }
+    }
+
+    /// Row of a file that is not parsed, e.g. a `junk` file or an `archive`.
+    pub fn from_file(path: &Path, media_type: FileType, action: Action, elapsed: Duration) -> Self {
+        Self {
+            source: path.to_path_buf(),
+            media_type: Some(media_type),
+            name: None,
+            season: None,
+            episode: None,
+            year: None,
+            provider: None,
+            confidence: None,
+            action,
+            destination: None,
+            reason: None,
+            duration_ms: elapsed.as_millis(),
+            skip: Skip::No,
+        }
+    }
+
+    pub fn from_error(path: &Path, error: impl Into<anyhow::Error>, elapsed: Duration) -> Self {
+        Self {
+            source: path.to_path_buf(),
+            media_type: None,
+            name: None,
+            season: None,
+            episode: None,
+            year: None,
+            provider: None,
+            confidence: None,
+            action: Action::Error,
+            destination: None,
+            reason: Some(format!("{:#}", error.into())),
+            duration_ms: elapsed.as_millis(),
+            skip: Skip::No,
+        }
+    }
+
+    pub fn with_reason(mut self, reason: &str) -> Self {
+        self.reason = Some(reason.to_string());
+        self
+    }
+
+    pub fn with_skip(mut self, skip: Skip) -> Self {
+        self.skip = skip;
+        self
+    }
+}
+
+/// Write the rows as CSV when the path ends with `.csv`, as JSON otherwise.
+pub fn write_report(path: &Path, rows: &[ReportRow]) -> Result<()> {
+    let is_csv = path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("csv"));
+
+    if is_csv {
+        let mut writer = csv::Writer::from_path(path)
+            .with_context(|| format!("Could not write report {:?}", path))?;
+        for row in rows {
+            writer.serialize(row)?;
+        }
+        writer.flush()?;
+    } else {
+        fs::write(path, serde_json::to_string_pretty(rows)?)
+            .with_context(|| format!("Could not write report {:?}", path))?;
+    }
+
+    Ok(())
+}
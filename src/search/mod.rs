@@ -1,5 +1,6 @@
-pub(crate) mod search_tmdb;
-pub(crate) mod search_tvmaze;
 pub(crate) mod result;
+#[allow(clippy::module_inception)]
 mod search;
-pub(crate) mod strings;
\ No newline at end of file
+pub(crate) mod search_tmdb;
+pub(crate) mod search_tvmaze;
+pub(crate) mod strings;
@@ -1,5 +1,7 @@
+use serde::{Deserialize, Serialize};
 
-#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum MediaType {
     Series,
     Movie,
@@ -11,6 +13,7 @@ pub struct MediaResult{
     pub year: String,
     pub media_type: MediaType,
     pub accuracy: i64,
+    pub genres: Vec<String>,
 }
 
 impl MediaResult {
@@ -20,6 +23,7 @@ impl MediaResult {
             year,
             media_type,
             accuracy,
+            genres: Vec::new(),
         }
     }
 
@@ -29,6 +33,13 @@ impl MediaResult {
     }
 }
 
+/// Database result picked for a media, with the accuracy of its title.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SearchMatch {
+    pub provider: String,
+    pub confidence: i64,
+}
+
 pub fn get_highest_accuracy(results: Vec<MediaResult>) -> Option<MediaResult> {
     let mut highest_accuracy: i64 = 0;
     let mut closest_result: Option<MediaResult> = None;
@@ -1,4 +1,4 @@
-const TMDB_API_KEY: &'static str = "8c0878d2f669e85ac223e0680290fbf4";
+const TMDB_API_KEY: &str = "8c0878d2f669e85ac223e0680290fbf4";
 
 use anyhow::{Error, Ok};
 use serde::{Deserialize, Serialize};
@@ -51,16 +51,23 @@ impl MovieDBResult {
             MediaType::Movie,
             0,
         );
+        result.genres = self
+            .genre_ids
+            .iter()
+            .flatten()
+            .filter_map(|id| genre_name(*id))
+            .map(|genre| genre.to_string())
+            .collect();
         let mut movie_title: String = "".to_string();
-        if self.original_title.is_some() && self.original_title.clone().unwrap() != "".to_string() {
+        if self.original_title.is_some() && self.original_title.clone().unwrap() != "" {
             movie_title = self.original_title.clone().unwrap();
-        } else if self.title.is_some() && self.title.clone().unwrap() != "".to_string() {
+        } else if self.title.is_some() && self.title.clone().unwrap() != "" {
             movie_title = self.title.clone().unwrap();
         }
 
-        if movie_title != "".to_string()
+        if !movie_title.is_empty()
             && self.release_date.is_some()
-            && self.release_date.clone().unwrap() != "".to_string()
+            && self.release_date.clone().unwrap() != ""
         {
             result.media_type = MediaType::Movie;
             result.title = movie_title.clone();
@@ -79,9 +86,9 @@ impl MovieDBResult {
             }
             return Ok(Some(result));
         } else if self.name.is_some()
-            && self.name.clone().unwrap() != "".to_string()
+            && self.name.clone().unwrap() != ""
             && self.first_air_date.is_some()
-            && self.first_air_date.clone().unwrap() != "".to_string()
+            && self.first_air_date.clone().unwrap() != ""
         {
             result.media_type = MediaType::Series;
             result.title = self.name.clone().unwrap();
@@ -104,6 +111,41 @@ impl MovieDBResult {
     }
 }
 
+/// Names of the TMDB movie and TV genres, the search results only carry their ids.
+fn genre_name(id: i32) -> Option<&'static str> {
+    let name = match id {
+        12 => "Adventure",
+        14 => "Fantasy",
+        16 => "Animation",
+        18 => "Drama",
+        27 => "Horror",
+        28 => "Action",
+        35 => "Comedy",
+        36 => "History",
+        37 => "Western",
+        53 => "Thriller",
+        80 => "Crime",
+        99 => "Documentary",
+        878 => "Science Fiction",
+        9648 => "Mystery",
+        10402 => "Music",
+        10749 => "Romance",
+        10751 => "Family",
+        10752 => "War",
+        10759 => "Action & Adventure",
+        10762 => "Kids",
+        10763 => "News",
+        10764 => "Reality",
+        10765 => "Sci-Fi & Fantasy",
+        10766 => "Soap",
+        10767 => "Talk",
+        10768 => "War & Politics",
+        10770 => "TV Movie",
+        _ => return None,
+    };
+    Some(name)
+}
+
 pub(crate) fn search_movie_db(
     query: &str,
     year: Option<&str>,
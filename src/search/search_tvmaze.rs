@@ -35,16 +35,14 @@ pub fn search_tvmaze(
         {
             if let Some(year_match) = captures.get(1) {
                 let accuracy = accuracy(query, &tv_maze_result.show.name);
-                let media_type = match media_type {
-                    MediaType::Series => MediaType::Series,
-                    MediaType::Movie => MediaType::Movie,
-                };
-                results.push(MediaResult::new(
+                let mut result = MediaResult::new(
                     tv_maze_result.show.name.clone(),
                     year_match.as_str().to_string(),
                     media_type,
                     accuracy,
-                ));
+                );
+                result.genres = tv_maze_result.show.genres.clone();
+                results.push(result);
             }
         }
     }
@@ -52,6 +50,40 @@ pub fn search_tvmaze(
     Ok(results)
 }
 
+/// Titles of the specials of the show best matching `query`, in airing order.
+pub fn search_specials(query: &str) -> Result<Vec<String>, Error> {
+    let url = single_search_url(query)?;
+    let show: Show = ureq::get(&url).call()?.into_json()?;
+    let Some(id) = show.id else {
+        return Ok(Vec::new());
+    };
+
+    // Specials are listed among the episodes of their season, without a number
+    let url = format!("http://api.tvmaze.com/shows/{}/episodes?specials=1", id);
+    let episodes: Vec<TvMazeEpisode> = ureq::get(&url).call()?.into_json()?;
+    Ok(episodes
+        .into_iter()
+        .filter(|episode| episode.number.is_none())
+        .map(|episode| episode.name)
+        .collect())
+}
+
+/// Show lookup URL, with the query percent-encoded since a name can hold `&` or `#`.
+fn single_search_url(query: &str) -> Result<String, Error> {
+    Ok(format!(
+        "http://api.tvmaze.com/singlesearch/shows?{}",
+        serde_urlencoded::to_string([("q", query)])?
+    ))
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct TvMazeEpisode {
+    pub name: String,
+    pub season: Option<u32>,
+    pub number: Option<u32>,
+    pub airdate: Option<String>,
+}
+
 #[derive(Deserialize, Serialize)]
 pub struct Links {
     pub previousepisode: Previousepisode,
@@ -133,3 +165,16 @@ pub struct TvMazeResult {
     pub score: Option<f64>,
     pub show: Show,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_single_search_url() {
+        assert_eq!(
+            single_search_url("Tom & Jerry #1").unwrap(),
+            "http://api.tvmaze.com/singlesearch/shows?q=Tom+%26+Jerry+%231"
+        );
+    }
+}
@@ -28,6 +28,70 @@ use regex::Regex;
 pub static YEARSTR: Lazy<Regex> = Lazy::new(|| Regex::new(r"(19\d\d|20\d\d)").unwrap());
 pub static GETYEAR: Lazy<Regex> =
     Lazy::new(|| Regex::new(&format!(r"\b{}\b", YEARSTR.as_str())).unwrap());
+pub static RESOLUTION: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?i)\b(?:(\d{3,4})[pi]|4K|UHD)\b").unwrap());
+pub static LANGUAGES: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?i)\b(MULTI|VOSTFR|VOST|VFF|VFQ|VFI|VF|TRUEFRENCH|FRENCH|SUBFRENCH|ENGLISH|GERMAN|SPANISH|ITALIAN|JAPANESE)\b").unwrap()
+});
+pub static SOURCE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"(?i)\b(REMUX|BLU-?RAY|BDRIP|BRRIP|WEB-?DL|WEB-?RIP|WEB|HDTV|DVDRIP|DVD|HDRIP|CAM)\b",
+    )
+    .unwrap()
+});
+pub static VIDEO_CODEC: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?i)\b(x\.?264|h\.?264|AVC|x\.?265|h\.?265|HEVC|AV1|XVID|DIVX|VP9)\b").unwrap()
+});
+pub static AUDIO_CODEC: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?i)(?:^|[^a-z0-9])(TRUEHD|DTS-HD[ .]?MA|DTS-HD|DTS-X|DTS|E-?AC-?3|DDP|DD\+|AC-?3|DD|AAC|FLAC|OPUS|MP3)(?:[^a-z]|$)").unwrap()
+});
+pub static AUDIO_CHANNELS: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?:^|[^0-9])([1-9]\.[01])(?:[^0-9]|$)").unwrap());
+pub static ATMOS: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?i)\bATMOS\b").unwrap());
+pub static HDR: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?i)(?:^|[^a-z0-9])(DV|DOVI|DOLBY[ .]?VISION|HDR10\+|HDR10PLUS|HDR10|HDR|HLG)(?:[^a-z0-9+]|$)").unwrap()
+});
+pub static EDITION: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?i)\b(EXTENDED(?:[ .](?:CUT|EDITION))?|DIRECTOR'?S[ .]CUT|IMAX|UNRATED|THEATRICAL(?:[ .]CUT)?|REMASTERED|UNCUT|FINAL[ .]CUT|CRITERION)\b").unwrap()
+});
+pub static REPACK: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?i)\b(REPACK\d?|RERIP)\b").unwrap());
+pub static PROPER: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?i)\bPROPER\b").unwrap());
+/// Formatting of a subtitle line: HTML tags, ASS overrides and line breaks.
+pub static SUBTITLE_TAGS: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"<[^>]*>|\{[^}]*\}|\\[Nnh]").unwrap());
+/// Override block of an ASS line, e.g. `{\\an8}` or `{\\i1}`.
+pub static ASS_OVERRIDE: Lazy<Regex> = Lazy::new(|| Regex::new(r"\{[^}]*\}").unwrap());
+/// Tag of an SRT or VTT line, with its name, e.g. `<i>` or `<c.yellow>`.
+pub static HTML_TAG: Lazy<Regex> = Lazy::new(|| Regex::new(r"</?([^\s>]*)[^>]*>").unwrap());
+/// Season folder of a sorted library, e.g. `S02` or `Season 2`.
+pub static SEASON_DIR: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?i)^(?:S|Season[ ._]?)(\d{1,3})$").unwrap());
+/// Special of a series in a cleaned name, e.g. `SP01`, `OVA 2`, `OAD` or `Special`.
+pub static SPECIAL: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?i)\b(?:SP(\d{1,3})|(?:OVA|OAD|ONA|Specials?)(?:[ ]?(\d{1,3}))?)\b").unwrap()
+});
+/// Episode of season 0, e.g. `S00E05`.
+pub static SEASON_ZERO: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?i)\bS00E\d").unwrap());
+/// Release group at the end of the name (`...x264-GROUP`) or at the start (`[Group] ...`).
+pub static GROUP: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^\[([^\]]+)\]|-([A-Za-z0-9]+)(?:\[[^\]]*\])?$").unwrap());
+/// Plex suffix of an extra, e.g. `Movie-trailer` or `Movie - Behind the Scenes`.
+pub static EXTRA_SUFFIX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"(?i)\s*-\s*(trailer|featurette|behind[ ._]?the[ ._]?scenes|deleted|interview|other)$",
+    )
+    .unwrap()
+});
+/// Word of an extra in a name split on spaces, e.g. `sample` or `making of`.
+pub static EXTRA_WORDS: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?i)\b(sample|trailer|teaser|featurette|behind the scenes|making of|deleted scenes?|interview)\b")
+        .unwrap()
+});
+/// Numbered volume of a RAR set, e.g. `.part01.rar`.
+pub static RAR_PART: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?i)\.part(\d+)\.rar$").unwrap());
+/// Extension of an archive volume, e.g. `.zip`, `.rar` or `.r00`.
+pub static ARCHIVE_VOLUME: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?i)\.(?:zip|rar|r\d{2,3})$").unwrap());
 pub static UNWANTED_WORDS_FILE: Lazy<String> = Lazy::new(|| {
     let base_dirs = BaseDirs::new().unwrap();
     let dir_path = base_dirs.data_local_dir().join("MediaSort");
@@ -42,7 +106,7 @@ pub static UNWANTED_WORDS_FILE: Lazy<String> = Lazy::new(|| {
 });
 
 pub fn accuracy(a: &str, b: &str) -> i64 {
-    return 100 - dist(a, b);
+    100 - dist(a, b)
 }
 
 pub fn dist(a: &str, b: &str) -> i64 {
@@ -65,8 +129,8 @@ pub fn dist(a: &str, b: &str) -> i64 {
     let mut cur = vec![0; len_b];
 
     // initialize string b
-    for i in 1..len_b {
-        cur[i] = i;
+    for (i, cell) in cur.iter_mut().enumerate().skip(1) {
+        *cell = i;
     }
 
     // calculate edit distance
@@ -109,6 +173,12 @@ pub fn clean_filename(filename_to_clean: &str) -> Result<String> {
     // Replace certain characters with spaces
     cleaned = cleaned.replace(&['.', '_', '-', '+'][..], " ");
 
+    // Keep a year in parentheses, as in `Movie (2010).mkv`
+    cleaned = Regex::new(&format!(r"\({}\)", YEARSTR.as_str()))
+        .unwrap()
+        .replace_all(&cleaned, " $1 ")
+        .to_string();
+
     // Remove unwanted patterns like [] and () content
     cleaned = Regex::new(r"\[.*?\]")
         .unwrap()
@@ -135,7 +205,7 @@ fn read_unwanted_words(file_path: &str) -> io::Result<Vec<String>> {
     let reader = io::BufReader::new(file);
     let words = reader
         .lines()
-        .filter_map(|line| line.ok())
+        .map_while(|line| line.ok())
         .map(|line| line.trim().to_string())
         .filter(|line| !line.is_empty())
         .collect::<Vec<String>>();
@@ -143,7 +213,7 @@ fn read_unwanted_words(file_path: &str) -> io::Result<Vec<String>> {
     Ok(words)
 }
 
-pub fn extract_series_name(filename_clean: &String) -> Result<String> {
+pub fn extract_series_name(filename_clean: &str) -> Result<String> {
     // First try string operations to avoid regex
     let name: Vec<&str> = filename_clean.split_whitespace().collect();
 
@@ -197,3 +267,36 @@ pub fn extract_series_name(filename_clean: &String) -> Result<String> {
 
     bail!("name not found")
 }
+
+/// Drop the characters Windows and SMB shares refuse in a file or folder name, and
+/// suffix the reserved device names.
+pub fn sanitize_filename(filename: &str) -> String {
+    let invalid_chars = ['<', '>', '"', '/', '|', '?', '*', ':'];
+    let reserved_names = [
+        "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+        "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+    ];
+    // Skip drive letter (e.g., "C:") when sanitizing
+    let (drive, rest) = if filename.len() > 2 && &filename[1..2] == ":" {
+        filename.split_at(2)
+    } else {
+        ("", filename)
+    };
+
+    // Sanitize the rest of the path, ignoring invalid characters
+    let sanitized: String = rest
+        .chars()
+        .filter(|c| !invalid_chars.contains(c))
+        .collect();
+    let sanitized = sanitized.trim().to_string();
+
+    // Ensure the sanitized filename is not a reserved name
+    let sanitized = if reserved_names.contains(&sanitized.as_str()) {
+        format!("{}_", sanitized)
+    } else {
+        sanitized
+    };
+
+    // Reattach the drive letter
+    format!("{}{}", drive, sanitized)
+}
@@ -0,0 +1,144 @@
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::episode::Episode;
+
+/// Extensions of the files moved along with the media or subtitle sharing their base name:
+/// release info, covers, chapters and VobSub indexes.
+const SIDECAR_EXTENSIONS: [&str; 8] = ["nfo", "jpg", "jpeg", "png", "tbn", "xml", "txt", "idx"];
+
+/// Extensions of the files left by downloads that no media needs.
+const JUNK_EXTENSIONS: [&str; 5] = ["txt", "url", "website", "lnk", "sfv"];
+
+/// Files added by release groups and trackers, lowercase.
+const JUNK_NAMES: [&str; 3] = ["rarbg.txt", "rarbg_do_not_mirror.exe", "www.yts.mx.jpg"];
+
+/// What to do with the junk files found next to the medias.
+#[derive(Serialize, Deserialize, ValueEnum, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum JunkPolicy {
+    /// Leave them in the input.
+    #[default]
+    Keep,
+    /// Delete them once a media of their folder was sorted.
+    Delete,
+}
+
+/// A file that belongs to a media or a VobSub `.sub` subtitle, e.g. `Movie.nfo` or
+/// `Movie-poster.jpg`, and is renamed after it.
+#[derive(Clone)]
+pub struct SidecarFile {
+    pub full_path: PathBuf,
+    /// Source of the media or subtitle the file belongs to.
+    pub owner: PathBuf,
+    /// Path inside the media directory, the new name of the owner with the rest of the
+    /// file name, e.g. `Movie (2010).nfo` or `Subtitles/Movie (2010).en.idx`.
+    pub path: PathBuf,
+    pub episode: Episode,
+}
+
+impl SidecarFile {
+    /// `owner_path` is where the owner goes inside the media directory.
+    pub fn new(
+        full_path: &Path,
+        owner: &Path,
+        owner_path: &Path,
+        episode: &Episode,
+    ) -> Option<Self> {
+        let suffix = suffix(full_path, owner)?;
+        let stem = owner_path.file_stem()?.to_string_lossy();
+        Some(Self {
+            full_path: full_path.to_path_buf(),
+            owner: owner.to_path_buf(),
+            path: owner_path.with_file_name(format!("{}{}", stem, suffix)),
+            episode: episode.clone(),
+        })
+    }
+}
+
+fn extension(path: &Path) -> String {
+    path.extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
+
+/// Rest of the name of a sidecar after the base name of its owner, e.g. `.nfo` or
+/// `-poster.jpg`. `None` when the file is not a sidecar of the owner. An `.idx` index
+/// only goes with its `.sub` pictures, and a `.sub` has no other sidecar.
+pub fn suffix(path: &Path, owner: &Path) -> Option<String> {
+    let extension = extension(path);
+    if path.parent() != owner.parent()
+        || !SIDECAR_EXTENSIONS.contains(&extension.as_str())
+        || (extension == "idx") != (self::extension(owner) == "sub")
+    {
+        return None;
+    }
+    let name = path.file_name()?.to_str()?;
+    let stem = owner.file_stem()?.to_str()?;
+    let suffix = name.strip_prefix(stem)?;
+    suffix
+        .starts_with(['.', '-', '_'])
+        .then(|| suffix.to_string())
+}
+
+/// Whether a file is known junk, like `RARBG.txt` or a `.url` shortcut.
+pub fn is_junk(path: &Path) -> bool {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    JUNK_NAMES.contains(&name.as_str()) || JUNK_EXTENSIONS.contains(&extension(path).as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cmd::sort::dry_run_tree;
+    use crate::subtitle::SubtitleLayout;
+
+    #[test]
+    fn test_sidecar_files() {
+        let episode = Episode::new_test("Show.S01E03.1080p.mkv", false);
+        let media = Path::new("Show - E03.mkv");
+        let sidecar =
+            |name: &str| SidecarFile::new(Path::new(name), &episode.full_path, media, &episode);
+
+        let nfo = sidecar("Show.S01E03.1080p.nfo").unwrap();
+        assert_eq!(nfo.path, PathBuf::from("Show - E03.nfo"));
+        let poster = sidecar("Show.S01E03.1080p-poster.jpg").unwrap();
+        assert_eq!(poster.path, PathBuf::from("Show - E03-poster.jpg"));
+        assert!(sidecar("Show.S01E03.1080p.exe").is_none());
+        assert!(sidecar("Show.S01E04.1080p.nfo").is_none());
+        assert!(sidecar("Show.S01E03.1080p.idx").is_none());
+        assert!(sidecar("Other/Show.S01E03.1080p.nfo").is_none());
+
+        let vobsub = Path::new("Show.S01E03.1080p.en.sub");
+        assert_eq!(
+            suffix(Path::new("Show.S01E03.1080p.en.idx"), vobsub).as_deref(),
+            Some(".idx")
+        );
+        assert_eq!(suffix(Path::new("Show.S01E03.1080p.en.nfo"), vobsub), None);
+
+        assert!(is_junk(Path::new("RARBG.txt")));
+        assert!(is_junk(Path::new("Downloaded from.url")));
+        assert!(!is_junk(Path::new("Show.S01E03.1080p.nfo")));
+
+        let lines = dry_run_tree(
+            &[episode],
+            &[],
+            &[nfo, poster],
+            SubtitleLayout::Folder,
+            |_| (PathBuf::from("Series"), "Series".to_string()),
+        );
+        assert_eq!(
+            lines[3..6],
+            [
+                "      ├─ Show - E03-poster.jpg",
+                "      ├─ Show - E03.mkv",
+                "      └─ Show - E03.nfo",
+            ]
+        );
+    }
+}
@@ -1,5 +1,28 @@
-use std::path::PathBuf;
-use crate::{episode::Episode, search::strings::*};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use crate::{
+  convert::SubtitleFormat,
+  episode::{split_file_name, Episode},
+  error::ParseError,
+  extract,
+  language::{self, SubtitleLanguage},
+  probe::SubtitleTrack,
+  release::ReleaseInfo,
+  search::strings::*,
+};
+
+/// Where sorted subtitles are placed.
+#[derive(Serialize, Deserialize, ValueEnum, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SubtitleLayout {
+  /// In a `Subtitles` folder next to the media.
+  #[default]
+  Folder,
+  /// Next to the media with the same base name, as Plex and Jellyfin expect.
+  Sidecar,
+}
 
 #[derive(Clone)]
 pub struct Subtitle {
@@ -7,36 +30,349 @@ pub struct Subtitle {
   pub filename_clean: String,
   pub episode: Episode,
   pub language: Option<String>,
+  pub forced: bool,
+  pub sdh: bool,
+  pub cc: bool,
+  /// Set from 2 when another subtitle would get the same name.
+  pub number: Option<u32>,
+  /// Format the subtitle is converted to when moved.
+  pub format: Option<SubtitleFormat>,
 }
 
 
 impl Subtitle {
-  pub fn new(full_path: PathBuf) -> Self {
-    let filename = full_path.file_name().unwrap().to_str().unwrap().to_string();
+  pub fn new(full_path: PathBuf) -> Result<Self, ParseError> {
+    let (filename, extension) = split_file_name(&full_path)?;
     let filename_clean = clean_filename(&filename).unwrap_or_default();
-    let extension = full_path.extension().unwrap().to_str().unwrap().to_string();
 
-    let name = extract_series_name(&filename_clean).unwrap();
-
-    Self {
-      episode: Episode {
-        full_path: full_path.clone(),
+    let mut episode = Episode {
         filename: filename.clone(),
         filename_clean: filename_clean.clone(),
         extension: extension.clone(),
-        name,
-        season: 0,
-        episode: 0,
-        is_movie: false,
-        year: None,
-      },
+        release: ReleaseInfo::parse(&filename),
+        ..Episode::blank(&full_path)
+    };
+    episode.parse_path()?;
+
+    Ok(Self {
+        episode,
         full_path,
         filename_clean,
         language: None,
-    }
+        forced: false,
+        sdh: false,
+        cc: false,
+        number: None,
+        format: None,
+    })
+  }
+
+  /// Sidecar for a text subtitle stream of a media, `None` for picture subtitles.
+  pub fn embedded(media: &Episode, track: &SubtitleTrack) -> Option<Self> {
+    let extension = extract::text_extension(track.codec.as_deref()?)?;
+
+    Some(Self {
+      full_path: media.full_path.with_extension(extension),
+      filename_clean: media.filename_clean.clone(),
+      episode: media.clone(),
+      language: track.language.as_deref().and_then(language::normalize),
+      forced: track.forced,
+      sdh: track.hearing_impaired,
+      cc: false,
+      number: None,
+      format: None,
+    })
   }
 
   pub fn set_episode(&mut self, episode: Episode) {
     self.episode = episode;
   }
-}
\ No newline at end of file
+
+  pub fn set_language(&mut self, language: SubtitleLanguage) {
+    self.language = language.code;
+    self.forced = language.forced;
+    self.sdh = language.sdh;
+    self.cc = language.cc;
+  }
+}
+
+/// Lowest score for a subtitle to be paired with a media.
+const MIN_MATCH_SCORE: u32 = 60;
+
+/// Pair each subtitle with the media it belongs to, returning the ones that matched none.
+pub fn match_subtitles(subtitles: &mut Vec<Subtitle>, medias: &[Episode]) -> Vec<Subtitle> {
+  let mut videos_per_folder: HashMap<&Path, usize> = HashMap::new();
+  for media in medias {
+    if let Some(folder) = media.full_path.parent() {
+      *videos_per_folder.entry(folder).or_default() += 1;
+    }
+  }
+
+  let mut unmatched = Vec::new();
+  for mut subtitle in std::mem::take(subtitles) {
+    let best = medias
+      .iter()
+      .filter_map(|media| {
+        let sole_video = media
+          .full_path
+          .parent()
+          .is_some_and(|folder| videos_per_folder.get(folder) == Some(&1));
+        subtitle.match_score(media, sole_video).map(|score| (score, media))
+      })
+      .filter(|(score, _)| *score >= MIN_MATCH_SCORE)
+      .max_by(|(a, media_a), (b, media_b)| {
+        a.cmp(b).then_with(|| media_b.full_path.cmp(&media_a.full_path))
+      });
+
+    match best {
+      Some((_, media)) => {
+        subtitle.set_episode(media.clone());
+        subtitles.push(subtitle);
+      }
+      None => unmatched.push(subtitle),
+    }
+  }
+
+  unmatched
+}
+
+impl Subtitle {
+  /// How likely the subtitle belongs to the media, from 0 to 100, `None` when its season,
+  /// episode or year contradicts it. `sole_video` tells whether the media is alone in
+  /// its folder.
+  pub fn match_score(&self, media: &Episode, sole_video: bool) -> Option<u32> {
+    if self.filename_clean == media.filename_clean {
+      return Some(100);
+    }
+
+    let parsed = &self.episode;
+    let known = |value: Option<u32>| value.filter(|&value| value > 0);
+    if let (Some(year), Some(media_year)) = (known(parsed.year), known(media.year)) {
+      if year != media_year {
+        return None;
+      }
+    }
+    if media.is_movie {
+      if !parsed.is_movie && parsed.episode > 0 {
+        return None;
+      }
+    } else if (parsed.episode > 0 && parsed.episode != media.episode)
+      || (parsed.season > 0 && media.season > 0 && parsed.season != media.season)
+    {
+      return None;
+    }
+
+    let location = location(&self.full_path, &media.full_path);
+    match location {
+      Location::MediaFolder => return Some(100),
+      Location::Folder if sole_video => return Some(90),
+      _ => {}
+    }
+    // An episode name alone does not tell which episode it is
+    if !media.is_movie && parsed.episode == 0 {
+      return None;
+    }
+
+    let numbers = if !media.is_movie || known(parsed.year).is_some() { 30 } else { 0 };
+    let folder = if location == Location::Folder { 20 } else { 0 };
+    Some(name_similarity(&parsed.name, &media.name) * 6 / 10 + numbers + folder)
+  }
+}
+
+#[derive(PartialEq)]
+enum Location {
+  Elsewhere,
+  /// In the folder of the media, or in its `Subs` folder.
+  Folder,
+  /// In a folder named after the media, e.g. `Subs/Show.S01E01/2_English.srt`.
+  MediaFolder,
+}
+
+fn location(subtitle: &Path, media: &Path) -> Location {
+  let is_subs = |dir: &Path| {
+    dir.file_name().is_some_and(|name| {
+      let name = name.to_string_lossy();
+      name.eq_ignore_ascii_case("subs") || name.eq_ignore_ascii_case("subtitles")
+    })
+  };
+  let (Some(dir), Some(media_dir)) = (subtitle.parent(), media.parent()) else {
+    return Location::Elsewhere;
+  };
+
+  if dir == media_dir || (is_subs(dir) && dir.parent() == Some(media_dir)) {
+    return Location::Folder;
+  }
+  match dir.parent() {
+    Some(subs) if is_subs(subs) && subs.parent() == Some(media_dir) => {
+      if dir.file_name() == media.file_stem() {
+        Location::MediaFolder
+      } else {
+        Location::Folder
+      }
+    }
+    _ => Location::Elsewhere,
+  }
+}
+
+/// Similarity of two names from 0 to 100, ignoring case, punctuation and the language
+/// tags left at the end of a subtitle name.
+fn name_similarity(subtitle: &str, media: &str) -> u32 {
+  let words = |name: &str| -> Vec<String> {
+    name
+      .split(|c: char| !c.is_alphanumeric())
+      .filter(|word| !word.is_empty())
+      .map(str::to_lowercase)
+      .collect()
+  };
+  let similarity = |a: &str, b: &str| {
+    let length = a.chars().count().max(b.chars().count()).max(1);
+    let distance = dist(a, b).max(0) as usize;
+    (100 - (distance * 100 / length).min(100)) as u32
+  };
+
+  let media = words(media).join(" ");
+  let mut subtitle = words(subtitle);
+  let mut best = similarity(&subtitle.join(" "), &media);
+  while subtitle.len() > 1 {
+    let last = &subtitle[subtitle.len() - 1];
+    if language::normalize(last).is_none() && !language::is_flag(last) {
+      break;
+    }
+    subtitle.pop();
+    best = best.max(similarity(&subtitle.join(" "), &media));
+  }
+
+  best
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::cmd::sort::dry_run_tree;
+
+  #[test]
+  fn test_subtitle_fuzzy_matching() {
+    let media = |dir: &str, name: &str, is_movie: bool| {
+      let mut episode = Episode::new_test(name, is_movie);
+      episode.full_path = Path::new(dir).join(name);
+      episode
+    };
+    let episodes = vec![
+      media("Downloads", "Show.S01E01.1080p.WEB.mkv", false),
+      media("Downloads", "Show.S01E02.1080p.WEB.mkv", false),
+      media("Movies/Inception", "Inception.2010.1080p.mkv", true),
+    ];
+
+    let mut subtitles: Vec<Subtitle> = [
+      "Subtitles/Show.S01E01.en.srt",
+      "Downloads/Subs/Show.S01E02.1080p.WEB/2_English.srt",
+      "Movies/Inception/Subs/English.srt",
+      "Subtitles/Other.Program.S01E01.en.srt",
+      "Subtitles/Show.S01E05.en.srt",
+    ]
+    .iter()
+    .map(|path| Subtitle::new(PathBuf::from(path)).unwrap())
+    .collect();
+
+    let unmatched = match_subtitles(&mut subtitles, &episodes);
+
+    let paired: Vec<(&str, &str)> = subtitles
+      .iter()
+      .map(|subtitle| {
+        (
+          subtitle.full_path.to_str().unwrap(),
+          subtitle.episode.full_path.to_str().unwrap(),
+        )
+      })
+      .collect();
+    assert_eq!(
+      paired,
+      vec![
+        (
+          "Subtitles/Show.S01E01.en.srt",
+          "Downloads/Show.S01E01.1080p.WEB.mkv"
+        ),
+        (
+          "Downloads/Subs/Show.S01E02.1080p.WEB/2_English.srt",
+          "Downloads/Show.S01E02.1080p.WEB.mkv"
+        ),
+        (
+          "Movies/Inception/Subs/English.srt",
+          "Movies/Inception/Inception.2010.1080p.mkv"
+        ),
+      ]
+    );
+    let unmatched: Vec<&Path> = unmatched
+      .iter()
+      .map(|subtitle| subtitle.full_path.as_path())
+      .collect();
+    assert_eq!(
+      unmatched,
+      vec![
+        Path::new("Subtitles/Other.Program.S01E01.en.srt"),
+        Path::new("Subtitles/Show.S01E05.en.srt")
+      ]
+    );
+  }
+
+  #[test]
+  fn test_subtitle_year() {
+    let dir = std::env::temp_dir().join("mediasort_subtitle_year");
+    std::fs::create_dir_all(&dir).unwrap();
+    let medias: Vec<Episode> = ["The.Thing.1982.1080p.mkv", "The.Thing.2011.1080p.mkv"]
+      .iter()
+      .map(|name| {
+        let path = dir.join(name);
+        std::fs::write(&path, "").unwrap();
+        Episode::new(&path, None).unwrap()
+      })
+      .collect();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let subtitle = Subtitle::new(PathBuf::from("Subtitles/The.Thing.2011.en.srt")).unwrap();
+    assert_eq!(subtitle.match_score(&medias[0], false), None);
+    assert!(subtitle.match_score(&medias[1], false).is_some());
+
+    let mut subtitles = vec![subtitle];
+    assert!(match_subtitles(&mut subtitles, &medias).is_empty());
+    assert_eq!(subtitles[0].episode.full_path, medias[1].full_path);
+  }
+
+  #[test]
+  fn test_embedded_subtitles() {
+    let episode = Episode::new_test("Show.S01E03.1080p.mkv", false);
+    let track = |codec: &str, language: Option<&str>, forced: bool| SubtitleTrack {
+      index: 2,
+      codec: Some(codec.to_string()),
+      language: language.map(str::to_string),
+      forced,
+      hearing_impaired: false,
+    };
+
+    let sidecar = Subtitle::embedded(&episode, &track("subrip", Some("fre"), true)).unwrap();
+    assert_eq!(sidecar.full_path, PathBuf::from("Show.S01E03.1080p.srt"));
+    assert_eq!(sidecar.language.as_deref(), Some("fr"));
+    assert!(sidecar.forced);
+    let unknown = Subtitle::embedded(&episode, &track("ass", None, false)).unwrap();
+    assert_eq!(unknown.full_path.extension().unwrap(), "ass");
+    assert!(
+      Subtitle::embedded(&episode, &track("hdmv_pgs_subtitle", Some("eng"), false)).is_none()
+    );
+
+    let lines = dry_run_tree(
+      &[episode],
+      &[sidecar, unknown],
+      &[],
+      SubtitleLayout::Sidecar,
+      |_| (PathBuf::from("Series"), "Series".to_string()),
+    );
+    assert_eq!(
+      lines[3..6],
+      [
+        "      ├─ Show - E03.ass",
+        "      ├─ Show - E03.fr.forced.srt",
+        "      └─ Show - E03.mkv",
+      ]
+    );
+  }
+}
@@ -67,14 +67,14 @@ impl SelectedTab {
     fn previous(self) -> Self {
         let current_index: usize = self as usize;
         let previous_index = current_index.saturating_sub(1);
-        Self::from_repr(previous_index).unwrap_or_else(|| Self::Profiles)
+        Self::from_repr(previous_index).unwrap_or(Self::Profiles)
     }
 
     /// Get the next tab, if there is no next tab circle back to the first tab.
     fn next(self) -> Self {
         let current_index: usize = self as usize;
         let next_index = current_index.saturating_add(1);
-        Self::from_repr(next_index).unwrap_or_else(|| Self::MediaSort)
+        Self::from_repr(next_index).unwrap_or(Self::MediaSort)
     }
 }
 